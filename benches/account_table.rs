@@ -0,0 +1,71 @@
+//! Throughput of account lookups on a generated multi-million-row file.
+//!
+//! run with `cargo bench --bench account_table`
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use toy_txn_engine::{
+    account::{Account, AccountTable},
+    ledger::Ledger,
+    record::Record,
+};
+
+mod common;
+
+const ROWS: u32 = 3_000_000;
+
+fn apply(account: &mut Account, record: &Record) {
+    let amount = record.amount.unwrap_or(0);
+    if record.r#type == "deposit" {
+        let _ = account.add_available(amount);
+    } else {
+        let _ = account.sub_available(amount);
+    }
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let rate = ROWS as f64 / elapsed.as_secs_f64();
+    println!("  {name:<28} {elapsed:>10.2?} {:>8.2} M rows/s", rate / 1e6);
+}
+
+fn main() {
+    let path = std::env::temp_dir().join("toy-txn-engine-account-table.csv");
+    common::generate(&path, ROWS).unwrap();
+
+    let records: Vec<Record> = common::read(&path)
+        .deserialize()
+        .map(Result::unwrap)
+        .collect();
+    println!("{ROWS} rows");
+
+    let start = Instant::now();
+    let mut accounts: HashMap<u16, Account> = HashMap::new();
+    for record in &records {
+        apply(accounts.entry(record.client).or_default(), record);
+    }
+    report("HashMap<u16, Account>", start.elapsed());
+
+    let start = Instant::now();
+    let mut accounts = AccountTable::new();
+    for record in &records {
+        apply(accounts.get_or_insert(record.client), record);
+    }
+    report("AccountTable", start.elapsed());
+
+    let start = Instant::now();
+    let mut accounts = AccountTable::with_capacity(AccountTable::MAX_CLIENTS);
+    for record in &records {
+        apply(accounts.get_or_insert(record.client), record);
+    }
+    report("AccountTable (preallocated)", start.elapsed());
+    drop(records);
+
+    let start = Instant::now();
+    let mut ledger = Ledger::new();
+    for result in common::read(&path).deserialize() {
+        ledger.process_transaction(result.unwrap()).unwrap();
+    }
+    report("Ledger from file", start.elapsed());
+
+    std::fs::remove_file(&path).unwrap();
+}
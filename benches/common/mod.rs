@@ -0,0 +1,31 @@
+//! Input shared by the benchmarks.
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+// write `rows` deposits and withdrawals for pseudo-random clients.
+pub fn generate(path: &Path, rows: u32) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "type,client,tx,amount")?;
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    for tx in 0..rows {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let client = state as u16;
+        let r#type = if tx % 3 == 0 { "withdrawal" } else { "deposit" };
+        writeln!(
+            out,
+            "{type},{client},{tx},{}.{:04}",
+            state % 100,
+            state % 10_000
+        )?;
+    }
+    out.flush()
+}
+
+pub fn read(path: &Path) -> csv::Reader<BufReader<File>> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(BufReader::new(File::open(path).unwrap()))
+}
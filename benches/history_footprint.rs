@@ -0,0 +1,83 @@
+//! Memory held by the transaction history per million transactions.
+//!
+//! run with `cargo bench --bench history_footprint`
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use toy_txn_engine::{ledger::Ledger, record::Record, transaction::Txn};
+
+// counts the bytes currently allocated by the process.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const TXNS: u32 = 1_000_000;
+const CLIENTS: u32 = 1_000;
+
+// half deposits and half withdrawals spread over `CLIENTS` accounts.
+fn record(tx: u32) -> Record {
+    let r#type = if tx.is_multiple_of(2) {
+        "deposit"
+    } else {
+        "withdrawal"
+    };
+    Record {
+        r#type: r#type.to_owned(),
+        client: (tx % CLIENTS) as u16,
+        tx,
+        amount: Some(1_0000),
+    }
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn main() {
+    // before: every deposit and withdrawal kept as a full `Txn`.
+    let baseline = ALLOCATED.load(Ordering::Relaxed);
+    let mut full_history: HashMap<u32, Txn> = HashMap::new();
+    for tx in 0..TXNS {
+        let txn = Txn::from_record(record(tx)).unwrap();
+        full_history.insert(txn.txn_id(), txn);
+    }
+    let before = ALLOCATED.load(Ordering::Relaxed) - baseline;
+    drop(full_history);
+
+    // after: the ledger, which keeps only disputable deposits.
+    let baseline = ALLOCATED.load(Ordering::Relaxed);
+    let mut ledger = Ledger::new();
+    for tx in 0..TXNS {
+        ledger.process_transaction(record(tx)).unwrap();
+    }
+    let after = ALLOCATED.load(Ordering::Relaxed) - baseline;
+
+    println!("history footprint per {TXNS} transactions");
+    println!(
+        "  full Txn history:        {:>8.2} MiB ({} entries)",
+        mib(before),
+        TXNS
+    );
+    println!(
+        "  ledger (compact history): {:>7.2} MiB ({} entries, incl. {} accounts)",
+        mib(after),
+        ledger.history_len(),
+        ledger.accounts().len()
+    );
+}
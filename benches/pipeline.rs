@@ -0,0 +1,38 @@
+//! Throughput of parsing on worker threads against a single thread.
+//!
+//! run with `cargo bench --bench pipeline`
+use std::time::{Duration, Instant};
+
+use toy_txn_engine::{ledger::Ledger, pipeline::process_pipelined, record::Record};
+
+mod common;
+
+const ROWS: u32 = 3_000_000;
+
+fn report(name: &str, elapsed: Duration) {
+    let rate = ROWS as f64 / elapsed.as_secs_f64();
+    println!("  {name:<28} {elapsed:>10.2?} {:>8.2} M rows/s", rate / 1e6);
+}
+
+fn main() {
+    let path = std::env::temp_dir().join("toy-txn-engine-pipeline.csv");
+    common::generate(&path, ROWS).unwrap();
+    println!("{ROWS} rows");
+
+    let start = Instant::now();
+    let mut ledger = Ledger::new();
+    for result in common::read(&path).deserialize() {
+        let record: Record = result.unwrap();
+        ledger.process_transaction(record).unwrap();
+    }
+    report("single thread", start.elapsed());
+
+    for parsers in [1, 2, 4, 8] {
+        let start = Instant::now();
+        let mut ledger = Ledger::new();
+        process_pipelined(&mut common::read(&path), parsers, &mut ledger).unwrap();
+        report(&format!("{parsers} parser threads"), start.elapsed());
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -2,14 +2,27 @@ use std::collections::HashSet;
 
 use crate::events::ProcessEvent;
 
-#[derive(Debug, Clone)]
+/// Balances of a single client, amounts in units of 0.0001.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Account {
+    /// funds which can be withdrawn.
     pub available: u128,
+    /// funds held by open disputes.
     pub held: u128,
+    /// ids of the deposits, and withdrawals with
+    /// `disputes_against_withdrawals`, currently under dispute.
     pub disputes: HashSet<u32>,
+    /// set by a chargeback, after which deposits
+    /// and withdrawals are ignored.
     pub frozen: bool,
 }
 
+impl Default for Account {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Account {
     pub fn new() -> Self {
         Account {
@@ -64,13 +77,149 @@ impl Account {
         self.frozen = true;
     }
 
-    pub fn total(&self) -> u128 {
-        if let Some(total) = self.available.checked_add(self.held) {
-            total
-        } else {
-            // handle deposit limit exceeded
-            // (for now default to max value)
-            u128::MAX
+    /// available plus held funds.
+    ///
+    /// Fails rather than saturating if the sum exceeds u128::MAX,
+    /// which the ledger does not let happen.
+    pub fn total(&self) -> Result<u128, ProcessEvent> {
+        self.available
+            .checked_add(self.held)
+            .ok_or_else(|| ProcessEvent::ExternalErr("total exceeds limit".to_owned()))
+    }
+
+    /// whether `amount` can be added to the account without
+    /// its total exceeding u128::MAX.
+    pub fn can_credit(&self, amount: u128) -> bool {
+        self.total()
+            .is_ok_and(|total| total.checked_add(amount).is_some())
+    }
+}
+
+/// Accounts indexed directly by client id.
+///
+/// Client ids are u16 so there are at most 65,536 accounts, which
+/// makes a dense vector cheaper than hashing the id on every record.
+/// Slots are grown up to the highest client id seen, and a bit per
+/// slot records whether the account has been opened.
+#[derive(Debug, Clone, Default)]
+pub struct AccountTable {
+    slots: Vec<Account>,
+    present: Vec<u64>,
+    len: usize,
+}
+
+impl AccountTable {
+    pub const MAX_CLIENTS: usize = u16::MAX as usize + 1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// allocate slots for client ids `0..clients` up front.
+    pub fn with_capacity(clients: usize) -> Self {
+        let mut table = Self::new();
+        table.grow(clients.min(Self::MAX_CLIENTS));
+        table
+    }
+
+    fn grow(&mut self, slots: usize) {
+        if slots > self.slots.len() {
+            self.slots.resize_with(slots, Account::new);
+            self.present.resize(slots.div_ceil(64), 0);
         }
     }
+
+    fn is_present(&self, index: usize) -> bool {
+        self.present
+            .get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains_key(&self, client_id: &u16) -> bool {
+        self.is_present(*client_id as usize)
+    }
+
+    pub fn get(&self, client_id: &u16) -> Option<&Account> {
+        let index = *client_id as usize;
+        self.is_present(index).then(|| &self.slots[index])
+    }
+
+    pub fn get_mut(&mut self, client_id: &u16) -> Option<&mut Account> {
+        let index = *client_id as usize;
+        self.is_present(index).then(|| &mut self.slots[index])
+    }
+
+    /// the account for `client_id`, opening it if it does not exist.
+    pub fn get_or_insert(&mut self, client_id: u16) -> &mut Account {
+        let index = client_id as usize;
+        if !self.is_present(index) {
+            self.grow(index + 1);
+            self.present[index / 64] |= 1 << (index % 64);
+            self.len += 1;
+        }
+        &mut self.slots[index]
+    }
+
+    /// open accounts in ascending client id order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &Account)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.is_present(*index))
+            .map(|(index, account)| (index as u16, account))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Account, AccountTable};
+
+    #[test]
+    fn test_total_limit() {
+        let mut account = Account::new();
+        account.available = u128::MAX - 5;
+        account.held = 5;
+        assert_eq!(account.total(), Ok(u128::MAX));
+        assert!(account.can_credit(0));
+        assert!(!account.can_credit(1));
+
+        // a total past the limit is an error, never a saturated value
+        account.held = 6;
+        assert!(account.total().is_err());
+        assert!(!account.can_credit(0));
+    }
+
+    #[test]
+    fn test_account_table() {
+        let mut table = AccountTable::new();
+        assert!(table.is_empty());
+        assert!(table.get(&7).is_none());
+
+        table.get_or_insert(7).available = 10;
+        table.get_or_insert(u16::MAX).held = 5;
+        table.get_or_insert(0);
+        // opening an existing account keeps its balance
+        table.get_or_insert(7);
+
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.get(&7).unwrap().available, 10);
+        assert_eq!(table.get(&u16::MAX).unwrap().held, 5);
+        assert!(!table.contains_key(&8));
+
+        // iteration is in client id order
+        let ids: Vec<u16> = table.iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![0, 7, u16::MAX]);
+
+        let table = AccountTable::with_capacity(AccountTable::MAX_CLIENTS);
+        assert!(table.is_empty());
+        assert!(table.get(&u16::MAX).is_none());
+    }
 }
@@ -1,33 +1,487 @@
 use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 use std::{env, process};
 
-use crate::events::ProcessEvent;
-use crate::ledger::Ledger;
-use crate::record::Record;
+use toy_txn_engine::diff::read_states;
+use toy_txn_engine::pipeline::process_pipelined;
+use toy_txn_engine::reconcile::{read_accounts, reconcile};
+use toy_txn_engine::record::parse_amount;
+use toy_txn_engine::sharded::process_sharded;
+use toy_txn_engine::snapshot::{ledger_at, seq_of_txn};
+use toy_txn_engine::summary::RunSummary;
+use toy_txn_engine::{
+    AccountsDiff, Auditor, DecisionLog, DisputeWindow, HttpServer, JsonlEventSink, LedgerBuilder,
+    LedgerConfig, Level, LogFormat, Metrics, ProcessEvent, Record, Server, SnapshotStore,
+    Statement, Tolerances,
+};
+
+const USAGE: &str = "usage:
+ cargo run -- [transactions file] [options]
+ cargo run -- reconcile [transactions file] [expected accounts file] [options]
+                         [--tolerance AMOUNT]
+ cargo run -- statement [events file] [client] [--format csv|json]
+ cargo run -- diff [before] [after] [--format text|json]
+ cargo run -- balance-at [events file] --seq N|--tx N [--client N]
+                         [--snapshot-dir DIR] [--config FILE]
+ cargo run -- serve [address] [--config FILE]
+ cargo run -- http [address] [--config FILE]
+
+options:
+ --config FILE             json file of ledger policies
+ --dispute-window-txns N   keep deposits disputable for N transactions
+ --dispute-window-secs N   keep deposits disputable for N seconds
+ --shards N                process accounts on N worker threads
+ --parse-threads N         parse records on N worker threads, not with
+                           --audit each or --metrics-file
+ --events FILE             write domain events to FILE as json lines
+ --trial-balance FILE      write the trial balance of the books to FILE
+ --summary FILE            write the run summary to FILE as json
+ --metrics-file FILE       write prometheus metrics to FILE as the input is
+                           processed, and at the end
+ --metrics-addr ADDR       serve prometheus metrics over http at ADDR
+ --log-level LEVEL         log the decision on each transaction at or below
+                           LEVEL: error, warn, info (rejections) or debug
+ --log-format text|json    format of the decision log (default text)
+ --log-file FILE           write the decision log to FILE (default stderr)
+ --audit each|end          check ledger invariants after each transaction,
+                           or once at the end
+ --snapshot-dir DIR        save snapshots of the ledger to DIR, with --events
+ --snapshot-every N        snapshot every N transactions (default 100000)";
+
+const SNAPSHOT_EVERY: u64 = 100_000;
+
+/// how often the metrics file is rewritten, in transactions.
+const METRICS_EVERY: u64 = 10_000;
+
+/// when to check the invariants of the ledger.
+#[derive(Clone, Copy, PartialEq)]
+enum Audit {
+    EachTxn,
+    End,
+}
+
+/// options which follow the transactions file.
+struct Options {
+    config: Option<String>,
+    dispute_window: Option<DisputeWindow>,
+    shards: Option<usize>,
+    parse_threads: Option<usize>,
+    events: Option<String>,
+    trial_balance: Option<String>,
+    summary: Option<String>,
+    metrics_file: Option<String>,
+    metrics_addr: Option<String>,
+    log_level: Option<Level>,
+    log_format: LogFormat,
+    log_file: Option<String>,
+    audit: Option<Audit>,
+    snapshot_dir: Option<String>,
+    snapshot_every: u64,
+    tolerance: Option<u128>,
+}
+
+impl Options {
+    fn from_args(args: &[String]) -> Option<Self> {
+        let mut options = Options {
+            config: None,
+            dispute_window: None,
+            shards: None,
+            parse_threads: None,
+            events: None,
+            trial_balance: None,
+            summary: None,
+            metrics_file: None,
+            metrics_addr: None,
+            log_level: None,
+            log_format: LogFormat::Text,
+            log_file: None,
+            audit: None,
+            snapshot_dir: None,
+            snapshot_every: SNAPSHOT_EVERY,
+            tolerance: None,
+        };
+        for pair in args.chunks(2) {
+            let [flag, value] = pair else {
+                return None;
+            };
+            match flag.as_str() {
+                "--config" => options.config = Some(value.clone()),
+                "--dispute-window-txns" => {
+                    let txns = value.parse().ok()?;
+                    options.dispute_window = Some(DisputeWindow::Transactions(txns));
+                }
+                "--dispute-window-secs" => {
+                    let secs = value.parse().ok()?;
+                    options.dispute_window = Some(DisputeWindow::Age(Duration::from_secs(secs)));
+                }
+                "--shards" => options.shards = Some(value.parse().ok()?),
+                "--parse-threads" => options.parse_threads = Some(value.parse().ok()?),
+                "--events" => options.events = Some(value.clone()),
+                "--trial-balance" => options.trial_balance = Some(value.clone()),
+                "--summary" => options.summary = Some(value.clone()),
+                "--metrics-file" => options.metrics_file = Some(value.clone()),
+                "--metrics-addr" => options.metrics_addr = Some(value.clone()),
+                "--log-level" => options.log_level = Some(value.parse().ok()?),
+                "--log-format" => {
+                    options.log_format = match value.as_str() {
+                        "text" => LogFormat::Text,
+                        "json" => LogFormat::Json,
+                        _ => return None,
+                    }
+                }
+                "--log-file" => options.log_file = Some(value.clone()),
+                "--audit" => {
+                    options.audit = match value.as_str() {
+                        "each" => Some(Audit::EachTxn),
+                        "end" => Some(Audit::End),
+                        _ => return None,
+                    }
+                }
+                "--snapshot-dir" => options.snapshot_dir = Some(value.clone()),
+                "--snapshot-every" => options.snapshot_every = value.parse().ok()?,
+                "--tolerance" => options.tolerance = parse_amount(value).ok()?,
+                _ => return None,
+            }
+        }
+        // sharding reads on a single thread.
+        if options.shards.is_some() && options.parse_threads.is_some() {
+            return None;
+        }
+        // events and audits need a single ledger observing the input in order.
+        let observed = options.events.is_some() || options.audit.is_some();
+        if options.shards.is_some() && observed {
+            return None;
+        }
+        // the pipeline applies whole batches, so nothing runs between
+        // its transactions.
+        let between_txns = options.audit == Some(Audit::EachTxn) || options.metrics_file.is_some();
+        if options.parse_threads.is_some() && between_txns {
+            return None;
+        }
+        // snapshots are taken between transactions, and point into the journal.
+        if options.snapshot_dir.is_some()
+            && (options.events.is_none()
+                || options.shards.is_some()
+                || options.parse_threads.is_some()
+                || options.snapshot_every == 0)
+        {
+            return None;
+        }
+        Some(options)
+    }
+}
+
+fn lock<T>(observer: &Mutex<T>) -> Result<MutexGuard<'_, T>, Box<dyn Error>> {
+    observer.lock().map_err(|_| "observer poisoned".into())
+}
+
+/// print the statement of a client from an events file.
+fn statement(args: &[String]) -> Result<ProcessEvent, Box<dyn Error>> {
+    let (path, client, json) = match args {
+        [path, client] => (path, client, false),
+        [path, client, flag, format] if flag == "--format" && format == "csv" => {
+            (path, client, false)
+        }
+        [path, client, flag, format] if flag == "--format" && format == "json" => {
+            (path, client, true)
+        }
+        _ => {
+            println!("{USAGE}");
+            process::exit(1);
+        }
+    };
+    let client = client.parse()?;
+    let journal = BufReader::new(File::open(path)?);
+    let statement = Statement::from_journal(client, journal)?;
+
+    let out = std::io::stdout().lock();
+    if json {
+        statement.write_json(out)?;
+        println!();
+    } else {
+        statement.write_csv(out)?;
+    }
+    Ok(ProcessEvent::ProcessComplete)
+}
+
+/// print the differences between two accounts csvs or snapshots.
+fn diff(args: &[String]) -> Result<ProcessEvent, Box<dyn Error>> {
+    let (before, after, json) = match args {
+        [before, after] => (before, after, false),
+        [before, after, flag, format] if flag == "--format" && format == "text" => {
+            (before, after, false)
+        }
+        [before, after, flag, format] if flag == "--format" && format == "json" => {
+            (before, after, true)
+        }
+        _ => {
+            println!("{USAGE}");
+            process::exit(1);
+        }
+    };
+    let diff = AccountsDiff::new(&read_states(before)?, &read_states(after)?)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        println!("{diff}");
+    }
+    Ok(ProcessEvent::ProcessComplete)
+}
+
+/// print accounts as they were at a point in the input, from an events file.
+fn balance_at(args: &[String]) -> Result<ProcessEvent, Box<dyn Error>> {
+    let usage = || -> ! {
+        println!("{USAGE}");
+        process::exit(1);
+    };
+    let Some((journal, flags)) = args.split_first() else {
+        usage();
+    };
+    let (mut seq, mut tx, mut client, mut snapshot_dir, mut config) =
+        (None, None, None, None, None);
+    for pair in flags.chunks(2) {
+        let [flag, value] = pair else {
+            usage();
+        };
+        match flag.as_str() {
+            "--seq" => seq = Some(value.parse::<u64>()?),
+            "--tx" => tx = Some(value.parse::<u32>()?),
+            "--client" => client = Some(value.parse::<u16>()?),
+            "--snapshot-dir" => snapshot_dir = Some(value),
+            "--config" => config = Some(value),
+            _ => usage(),
+        }
+    }
+    let seq = match (seq, tx) {
+        (Some(seq), None) => seq,
+        (None, Some(tx)) => {
+            seq_of_txn(journal, tx)?.ok_or_else(|| format!("txn {tx} is not in {journal}"))?
+        }
+        _ => usage(),
+    };
+    let config = match config {
+        Some(path) => LedgerConfig::from_json_file(path)?,
+        None => LedgerConfig::default(),
+    };
+    let store = snapshot_dir.map(SnapshotStore::new).transpose()?;
+
+    let ledger = ledger_at(config, journal, store.as_ref(), seq)?;
+    let out = &mut std::io::stdout().lock();
+    ledger.write_accounts_filtered(out, |id| client.is_none_or(|client| client == id))?;
+    Ok(ProcessEvent::ProcessComplete)
+}
+
+/// apply records sent over tcp, or over the http api when `http`
+/// is set, to one ledger until the process is stopped.
+fn serve(args: &[String], http: bool) -> Result<ProcessEvent, Box<dyn Error>> {
+    let (addr, config) = match args {
+        [addr] => (addr, None),
+        [addr, flag, path] if flag == "--config" => (addr, Some(path)),
+        _ => {
+            println!("{USAGE}");
+            process::exit(1);
+        }
+    };
+    let config = match config {
+        Some(path) => LedgerConfig::from_json_file(path)?,
+        None => LedgerConfig::default(),
+    };
+
+    let ledger = LedgerBuilder::from_config(config).build();
+    if http {
+        let server = HttpServer::bind(addr, ledger)?;
+        eprintln!("listening on http://{}", server.local_addr()?);
+        server.run()?;
+    } else {
+        let server = Server::bind(addr, ledger)?;
+        eprintln!("listening on {}", server.local_addr()?);
+        server.run()?;
+    }
+    Ok(ProcessEvent::ProcessComplete)
+}
 
 pub fn the_app() -> Result<ProcessEvent, Box<dyn Error>> {
     // begin preprocessing
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        println!("usage:\n cargo run -- [transactions file] ");
+        println!("{USAGE}");
         process::exit(1);
     }
+    // reconciling processes the transactions as usual, then compares
+    // the accounts against the expected accounts instead of printing them.
+    let (transactions, expected, args) = match args[1].as_str() {
+        "statement" => return statement(&args[2..]),
+        "balance-at" => return balance_at(&args[2..]),
+        "diff" => return diff(&args[2..]),
+        "serve" => return serve(&args[2..], false),
+        "http" => return serve(&args[2..], true),
+        "reconcile" if args.len() >= 4 => (&args[2], Some(&args[3]), &args[4..]),
+        "reconcile" => {
+            println!("{USAGE}");
+            process::exit(1);
+        }
+        _ => (&args[1], None, &args[2..]),
+    };
+
+    let options = match Options::from_args(args) {
+        Some(options) if expected.is_some() || options.tolerance.is_none() => options,
+        _ => {
+            println!("{USAGE}");
+            process::exit(1);
+        }
+    };
+
+    // flags take precedence over the config file.
+    let mut config = match &options.config {
+        Some(path) => LedgerConfig::from_json_file(path)?,
+        None => LedgerConfig::default(),
+    };
+    if let Some(dispute_window) = options.dispute_window {
+        config.dispute_window = dispute_window;
+    }
+
+    let auditor = options
+        .audit
+        .map(|_| Arc::new(Mutex::new(Auditor::new(&config))));
 
-    let file = File::open(&args[1])?;
+    let file = File::open(transactions)?;
     let mut reader = csv::ReaderBuilder::new()
         .trim(csv::Trim::All)
         .from_reader(BufReader::new(file));
 
+    let metrics =
+        (options.metrics_file.is_some() || options.metrics_addr.is_some()).then(Metrics::new);
+    if let (Some(metrics), Some(addr)) = (&metrics, &options.metrics_addr) {
+        metrics.serve(addr)?;
+    }
+    let decision_log = match (&options.log_level, &options.log_file) {
+        (None, None) => None,
+        (level, path) => {
+            let level = level.unwrap_or(Level::Info);
+            let log = match path {
+                Some(path) => {
+                    let out = BufWriter::new(File::create(path)?);
+                    DecisionLog::new(level, options.log_format, out)
+                }
+                None => DecisionLog::new(level, options.log_format, std::io::stderr()),
+            };
+            Some(log)
+        }
+    };
+    let build_ledger = || {
+        let mut builder = LedgerBuilder::from_config(config.clone());
+        if let Some(metrics) = &metrics {
+            builder = builder.metrics(metrics.clone());
+        }
+        if let Some(log) = &decision_log {
+            builder = builder.decision_log(log.clone());
+        }
+        builder.build()
+    };
+
     // begin processing
-    let mut ledger = Ledger::new();
-    for result in reader.deserialize() {
-        let record: Record = result?;
-        ledger.process_transaction(record)?;
+    let started = Instant::now();
+    let ledger = if let Some(shards) = options.shards {
+        process_sharded(&mut reader, shards, build_ledger)?
+    } else {
+        let mut ledger = build_ledger();
+        if let Some(auditor) = &auditor {
+            ledger.subscribe(auditor.clone());
+        }
+        let journal = match &options.events {
+            Some(path) => {
+                let out = BufWriter::new(File::create(path)?);
+                let journal = Arc::new(Mutex::new(JsonlEventSink::new(out)));
+                ledger.subscribe(journal.clone());
+                Some(journal)
+            }
+            None => None,
+        };
+        let snapshots = options
+            .snapshot_dir
+            .as_ref()
+            .map(SnapshotStore::new)
+            .transpose()?;
+        if let Some(parsers) = options.parse_threads {
+            process_pipelined(&mut reader, parsers, &mut ledger)?;
+        } else {
+            for result in reader.deserialize() {
+                let record: Record = result?;
+                ledger.process_transaction(record)?;
+                if let (Some(auditor), Some(Audit::EachTxn)) = (&auditor, options.audit) {
+                    lock(auditor)?.check(&ledger);
+                }
+                if let (Some(metrics), Some(path)) = (&metrics, &options.metrics_file) {
+                    if ledger.seq().is_multiple_of(METRICS_EVERY) {
+                        metrics.write_file(path)?;
+                    }
+                }
+                if let (Some(store), Some(journal)) = (&snapshots, &journal) {
+                    if ledger.seq().is_multiple_of(options.snapshot_every) {
+                        let offset = lock(journal)?.bytes_written();
+                        store.save(&ledger.snapshot(offset))?;
+                    }
+                }
+            }
+        }
+        ledger
+    };
+    let elapsed = started.elapsed();
+    if let Some(log) = &decision_log {
+        log.flush()?;
+    }
+    if let (Some(metrics), Some(path)) = (&metrics, &options.metrics_file) {
+        metrics.write_file(path)?;
     }
 
-    ledger.print_accounts()?;
-    Ok(ProcessEvent::ProcessComplete)
+    // every check is reported before the run fails on any of them.
+    let mut failures = Vec::new();
+    if let Some(path) = expected {
+        let expected = read_accounts(BufReader::new(File::open(path)?))?;
+        let tolerances = Tolerances::uniform(options.tolerance.unwrap_or(0));
+        let report = reconcile(&ledger, &expected, &tolerances)?;
+        println!("{report}");
+        if !report.matched() {
+            failures.push(format!("accounts do not reconcile with {path}"));
+        }
+    } else {
+        ledger.print_accounts()?;
+    }
+    let summary = RunSummary::new(&ledger, elapsed);
+    eprintln!("{summary}");
+    if let Some(path) = &options.summary {
+        fs::write(path, serde_json::to_string_pretty(&summary)? + "\n")?;
+    }
+
+    if let Some(auditor) = &auditor {
+        let mut auditor = lock(auditor)?;
+        auditor.check_all(&ledger);
+        let report = auditor.report();
+        eprintln!("{report}");
+        if !report.passed() {
+            failures.push(format!(
+                "audit found {} violations",
+                report.violations.len()
+            ));
+        }
+    }
+
+    if let Some(path) = &options.trial_balance {
+        let trial_balance = ledger.books().trial_balance()?;
+        fs::write(path, format!("{trial_balance}\n"))?;
+        if !trial_balance.is_balanced() {
+            failures.push("books do not balance".to_owned());
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(ProcessEvent::ProcessComplete)
+    } else {
+        Ok(ProcessEvent::ExternalErr(failures.join(", ")))
+    }
 }
@@ -0,0 +1,428 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+use crate::{
+    account::Account,
+    books::BookAccount,
+    config::LedgerConfig,
+    events::ProcessEvent,
+    ledger::Ledger,
+    observer::{DomainEvent, LedgerObserver},
+    record::format_amount,
+    transaction::{TxnKind, TxnState},
+};
+
+/// An invariant the ledger must hold between transactions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Invariant {
+    /// an account's held funds equal the amounts of the
+    /// transactions in its `disputes`, all of which are disputed.
+    HeldMatchesDisputes,
+    /// an account's total equals its deposits less its
+    /// withdrawals and chargebacks.
+    TotalMatchesFlows,
+    /// a frozen account has no deposits or withdrawals applied,
+    /// unless the config allows them.
+    FrozenAccountUnchanged,
+    /// the books agree with the accounts, and balance.
+    BooksMatchAccounts,
+}
+
+impl Display for Invariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Invariant::HeldMatchesDisputes => write!(f, "held matches disputes"),
+            Invariant::TotalMatchesFlows => write!(f, "total matches flows"),
+            Invariant::FrozenAccountUnchanged => write!(f, "frozen account unchanged"),
+            Invariant::BooksMatchAccounts => write!(f, "books match accounts"),
+        }
+    }
+}
+
+/// A broken invariant, found after the `seq`th transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub seq: u64,
+    /// the client whose account broke the invariant,
+    /// `None` for invariants over the whole ledger.
+    pub client: Option<u16>,
+    pub invariant: Invariant,
+    pub detail: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "seq {}", self.seq)?;
+        if let Some(client) = self.client {
+            write!(f, " client {client}")?;
+        }
+        write!(f, " {}: {}", self.invariant, self.detail)
+    }
+}
+
+// what the auditor expects of a client's account, from its events.
+#[derive(Debug, Clone, Default)]
+struct Expected {
+    total: u128,
+    frozen: bool,
+}
+
+/// Checks the invariants of a ledger it observes.
+///
+/// The auditor keeps its own totals per client from the events the
+/// ledger emits, and compares them with the ledger's accounts, history
+/// and books when asked to check. Subscribe it to a ledger through an
+/// `Arc<Mutex<Auditor>>` to keep a handle on it.
+#[derive(Debug, Default)]
+pub struct Auditor {
+    frozen_ignores_deposits: bool,
+    frozen_ignores_withdrawals: bool,
+    expected: HashMap<u16, Expected>,
+    // clients with events since the last check.
+    touched: BTreeSet<u16>,
+    violations: Vec<Violation>,
+    seq: u64,
+}
+
+impl Auditor {
+    /// An auditor for a ledger built with `config`.
+    pub fn new(config: &LedgerConfig) -> Self {
+        Self {
+            frozen_ignores_deposits: config.frozen_ignores_deposits,
+            frozen_ignores_withdrawals: config.frozen_ignores_withdrawals,
+            ..Self::default()
+        }
+    }
+
+    fn violation(&mut self, client: Option<u16>, invariant: Invariant, detail: String) {
+        self.violations.push(Violation {
+            seq: self.seq,
+            client,
+            invariant,
+            detail,
+        });
+    }
+
+    fn credit(&mut self, client: u16, amount: u128) {
+        let expected = self.expected.entry(client).or_default();
+        match expected.total.checked_add(amount) {
+            Some(total) => expected.total = total,
+            None => self.violation(
+                Some(client),
+                Invariant::TotalMatchesFlows,
+                "flows exceed the amount limit".to_owned(),
+            ),
+        }
+    }
+
+    fn debit(&mut self, client: u16, amount: u128) {
+        let expected = self.expected.entry(client).or_default();
+        if let Some(total) = expected.total.checked_sub(amount) {
+            expected.total = total;
+            return;
+        }
+        let detail = format!(
+            "{} taken from a total of {}",
+            format_amount(amount),
+            format_amount(expected.total)
+        );
+        self.violation(Some(client), Invariant::TotalMatchesFlows, detail);
+    }
+
+    fn is_frozen(&self, client: u16) -> bool {
+        self.expected.get(&client).is_some_and(|e| e.frozen)
+    }
+
+    /// Check the accounts with events since the last check.
+    pub fn check(&mut self, ledger: &Ledger) {
+        for client in std::mem::take(&mut self.touched) {
+            self.check_account(ledger, client);
+        }
+    }
+
+    /// Check every account, and the invariants over the whole ledger.
+    pub fn check_all(&mut self, ledger: &Ledger) {
+        self.touched.clear();
+        for (client, _) in ledger.accounts().iter() {
+            self.check_account(ledger, client);
+        }
+
+        let books = ledger.books();
+        let held = ledger
+            .accounts()
+            .iter()
+            .try_fold(0u128, |held, (_, account)| held.checked_add(account.held));
+        let suspense = books.balance(BookAccount::DisputeSuspense);
+        if held != Some(suspense.credit) || suspense.debit != 0 {
+            self.violation(
+                None,
+                Invariant::BooksMatchAccounts,
+                format!(
+                    "dispute suspense is {}, accounts hold {}",
+                    format_amount(suspense.credit),
+                    held.map_or("more than the amount limit".to_owned(), format_amount)
+                ),
+            );
+        }
+        match books.trial_balance() {
+            Ok(trial_balance) if trial_balance.is_balanced() => {}
+            Ok(trial_balance) => self.violation(
+                None,
+                Invariant::BooksMatchAccounts,
+                format!(
+                    "books do not balance, debits {} credits {}",
+                    format_amount(trial_balance.total_debit),
+                    format_amount(trial_balance.total_credit)
+                ),
+            ),
+            Err(err) => self.violation(None, Invariant::BooksMatchAccounts, err.to_string()),
+        }
+    }
+
+    fn check_account(&mut self, ledger: &Ledger, client: u16) {
+        let Some(account) = ledger.account(client) else {
+            if self.expected.get(&client).is_some_and(|e| e.total != 0) {
+                self.violation(
+                    Some(client),
+                    Invariant::TotalMatchesFlows,
+                    "account missing".to_owned(),
+                );
+            }
+            return;
+        };
+
+        self.check_held(ledger, client, account);
+
+        let expected = self.expected.get(&client).map_or(0, |e| e.total);
+        match account.total() {
+            Ok(total) if total == expected => {}
+            Ok(total) => self.violation(
+                Some(client),
+                Invariant::TotalMatchesFlows,
+                format!(
+                    "total is {}, flows add up to {}",
+                    format_amount(total),
+                    format_amount(expected)
+                ),
+            ),
+            Err(err) => self.violation(Some(client), Invariant::TotalMatchesFlows, err.to_string()),
+        }
+
+        let balance = ledger.books().balance(BookAccount::Client(client));
+        if balance.credit != account.available || balance.debit != 0 {
+            self.violation(
+                Some(client),
+                Invariant::BooksMatchAccounts,
+                format!(
+                    "available is {}, client book has credit {} debit {}",
+                    format_amount(account.available),
+                    format_amount(balance.credit),
+                    format_amount(balance.debit)
+                ),
+            );
+        }
+    }
+
+    fn check_held(&mut self, ledger: &Ledger, client: u16, account: &Account) {
+        let mut disputed = Some(0u128);
+        for &txn_id in &account.disputes {
+            match ledger.txn(txn_id) {
+                Some(entry) if entry.state == TxnState::Disputed => {
+                    disputed = disputed.and_then(|sum| sum.checked_add(entry.amount()));
+                }
+                Some(entry) => self.violation(
+                    Some(client),
+                    Invariant::HeldMatchesDisputes,
+                    format!("txn {txn_id} is in disputes but {:?}", entry.state),
+                ),
+                None => self.violation(
+                    Some(client),
+                    Invariant::HeldMatchesDisputes,
+                    format!("txn {txn_id} is in disputes but not in the history"),
+                ),
+            }
+        }
+        if disputed != Some(account.held) {
+            self.violation(
+                Some(client),
+                Invariant::HeldMatchesDisputes,
+                format!(
+                    "held is {}, disputed txns add up to {}",
+                    format_amount(account.held),
+                    disputed.map_or("more than the amount limit".to_owned(), format_amount)
+                ),
+            );
+        }
+    }
+
+    /// Every violation found so far, in the order it was found.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// A report of the violations found so far.
+    pub fn report(&self) -> AuditReport {
+        AuditReport {
+            violations: self.violations.clone(),
+        }
+    }
+}
+
+impl LedgerObserver for Auditor {
+    fn on_event(&mut self, seq: u64, event: &DomainEvent) -> Result<(), ProcessEvent> {
+        self.seq = seq;
+        match *event {
+            DomainEvent::Deposited { client, amount, .. } => {
+                if self.frozen_ignores_deposits && self.is_frozen(client) {
+                    self.violation(
+                        Some(client),
+                        Invariant::FrozenAccountUnchanged,
+                        format!("deposit of {} applied", format_amount(amount)),
+                    );
+                }
+                self.credit(client, amount);
+            }
+            DomainEvent::Withdrew { client, amount, .. } => {
+                if self.frozen_ignores_withdrawals && self.is_frozen(client) {
+                    self.violation(
+                        Some(client),
+                        Invariant::FrozenAccountUnchanged,
+                        format!("withdrawal of {} applied", format_amount(amount)),
+                    );
+                }
+                self.debit(client, amount);
+            }
+            // a disputed withdrawal may be returned, so it is held
+            // on top of the available funds until settled.
+            DomainEvent::DisputeOpened {
+                client,
+                kind: TxnKind::Withdrawal,
+                amount,
+                ..
+            } => self.credit(client, amount),
+            DomainEvent::Resolved {
+                client,
+                kind: TxnKind::Withdrawal,
+                amount,
+                ..
+            } => self.debit(client, amount),
+            DomainEvent::ChargedBack {
+                client,
+                kind: TxnKind::Deposit,
+                amount,
+                ..
+            } => self.debit(client, amount),
+            DomainEvent::AccountFrozen { client } => {
+                self.expected.entry(client).or_default().frozen = true;
+            }
+            _ => {}
+        }
+        self.touched.insert(event.client());
+        Ok(())
+    }
+}
+
+/// The violations found by an audit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditReport {
+    pub violations: Vec<Violation>,
+}
+
+impl AuditReport {
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl Display for AuditReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.passed() {
+            return write!(f, "audit passed");
+        }
+        write!(f, "audit found {} violations", self.violations.len())?;
+        for violation in &self.violations {
+            write!(f, "\n  {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{events::ProcessEvent, ledger::Ledger, transaction::Txn};
+
+    use super::{Auditor, Invariant};
+
+    #[test]
+    fn test_audit() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::builder().disputes_against_withdrawals(true).build();
+        let auditor = Arc::new(Mutex::new(Auditor::new(ledger.config())));
+        ledger.subscribe(auditor.clone());
+
+        let txns = [
+            Txn::Deposit {
+                client_id: 1,
+                txn_id: 1,
+                amount: 10_0000,
+            },
+            Txn::Withdraw {
+                client_id: 1,
+                txn_id: 2,
+                amount: 3_0000,
+            },
+            Txn::Dispute {
+                client_id: 1,
+                txn_id: 2,
+            },
+            Txn::Deposit {
+                client_id: 2,
+                txn_id: 3,
+                amount: 5_0000,
+            },
+            Txn::Dispute {
+                client_id: 2,
+                txn_id: 3,
+            },
+            Txn::ChargeBack {
+                client_id: 2,
+                txn_id: 3,
+            },
+            Txn::Deposit {
+                client_id: 2,
+                txn_id: 4,
+                amount: 5_0000,
+            },
+            Txn::Resolve {
+                client_id: 1,
+                txn_id: 2,
+            },
+        ];
+        for txn in txns {
+            ledger.process_txn(txn)?;
+            auditor.lock().unwrap().check(&ledger);
+        }
+        auditor.lock().unwrap().check_all(&ledger);
+        assert!(auditor.lock().unwrap().report().passed());
+
+        // break the ledger behind the auditor's back
+        ledger.accounts.get_mut(&1).unwrap().held += 1;
+        auditor.lock().unwrap().check_all(&ledger);
+
+        let report = auditor.lock().unwrap().report();
+        let invariants: Vec<Invariant> = report.violations.iter().map(|v| v.invariant).collect();
+        assert_eq!(
+            invariants,
+            vec![
+                Invariant::HeldMatchesDisputes,
+                Invariant::TotalMatchesFlows,
+                Invariant::BooksMatchAccounts,
+            ]
+        );
+        assert_eq!(report.violations[0].client, Some(1));
+        assert_eq!(report.violations[0].seq, 8);
+
+        Ok(())
+    }
+}
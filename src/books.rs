@@ -0,0 +1,410 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::ProcessEvent,
+    observer::DomainEvent,
+    record::decimal,
+    transaction::{Txn, TxnKind},
+};
+
+/// An account in the double entry books.
+///
+/// Every client account is backed by a book account holding its
+/// available funds. Money enters and leaves the ledger through the
+/// system accounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BookAccount {
+    /// the outside world, which deposits are paid in from
+    /// and withdrawals are paid out to.
+    ExternalFunding,
+    /// funds held by open disputes, for every client.
+    DisputeSuspense,
+    /// funds reversed by chargebacks.
+    ChargebackLosses,
+    /// the available funds of a client.
+    Client(u16),
+}
+
+impl BookAccount {
+    const SYSTEM: [BookAccount; 3] = [
+        BookAccount::ExternalFunding,
+        BookAccount::DisputeSuspense,
+        BookAccount::ChargebackLosses,
+    ];
+}
+
+impl Display for BookAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ExternalFunding => write!(f, "external funding"),
+            Self::DisputeSuspense => write!(f, "dispute suspense"),
+            Self::ChargebackLosses => write!(f, "chargeback losses"),
+            Self::Client(client) => write!(f, "client {client}"),
+        }
+    }
+}
+
+/// A balanced entry: `amount` is debited from one account
+/// and credited to another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Posting {
+    pub debit: BookAccount,
+    pub credit: BookAccount,
+    pub amount: u128,
+}
+
+impl Posting {
+    /// The entry which books a domain event, if it moves money.
+    ///
+    /// | event                    | debit             | credit            |
+    /// |--------------------------|-------------------|-------------------|
+    /// | deposit                  | external funding  | client            |
+    /// | withdrawal               | client            | external funding  |
+    /// | dispute of deposit       | client            | dispute suspense  |
+    /// | resolve of deposit       | dispute suspense  | client            |
+    /// | chargeback of deposit    | dispute suspense  | chargeback losses |
+    /// | dispute of withdrawal    | chargeback losses | dispute suspense  |
+    /// | resolve of withdrawal    | dispute suspense  | chargeback losses |
+    /// | chargeback of withdrawal | dispute suspense  | client            |
+    pub fn for_event(event: &DomainEvent) -> Option<Posting> {
+        use BookAccount::*;
+
+        let (debit, credit, amount) = match *event {
+            DomainEvent::Deposited { client, amount, .. } => {
+                (ExternalFunding, Client(client), amount)
+            }
+            DomainEvent::Withdrew { client, amount, .. } => {
+                (Client(client), ExternalFunding, amount)
+            }
+            DomainEvent::DisputeOpened {
+                client,
+                kind,
+                amount,
+                ..
+            } => match kind {
+                TxnKind::Deposit => (Client(client), DisputeSuspense, amount),
+                TxnKind::Withdrawal => (ChargebackLosses, DisputeSuspense, amount),
+            },
+            DomainEvent::Resolved {
+                client,
+                kind,
+                amount,
+                ..
+            } => match kind {
+                TxnKind::Deposit => (DisputeSuspense, Client(client), amount),
+                TxnKind::Withdrawal => (DisputeSuspense, ChargebackLosses, amount),
+            },
+            DomainEvent::ChargedBack {
+                client,
+                kind,
+                amount,
+                ..
+            } => match kind {
+                TxnKind::Deposit => (DisputeSuspense, ChargebackLosses, amount),
+                TxnKind::Withdrawal => (DisputeSuspense, Client(client), amount),
+            },
+            DomainEvent::AccountOpened { .. }
+            | DomainEvent::AccountFrozen { .. }
+            | DomainEvent::Expired { .. }
+            | DomainEvent::Rejected { .. } => return None,
+        };
+        Some(Posting {
+            debit,
+            credit,
+            amount,
+        })
+    }
+}
+
+/// The net balance of a book account, on the debit or
+/// the credit side. At most one side is non zero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    #[serde(with = "decimal")]
+    pub debit: u128,
+    #[serde(with = "decimal")]
+    pub credit: u128,
+}
+
+impl Balance {
+    fn post_debit(&mut self, amount: u128) -> Result<(), ProcessEvent> {
+        if self.credit >= amount {
+            self.credit -= amount;
+        } else {
+            let debit = amount - self.credit;
+            self.credit = 0;
+            self.debit = self.debit.checked_add(debit).ok_or_else(overflow)?;
+        }
+        Ok(())
+    }
+
+    fn post_credit(&mut self, amount: u128) -> Result<(), ProcessEvent> {
+        if self.debit >= amount {
+            self.debit -= amount;
+        } else {
+            let credit = amount - self.debit;
+            self.debit = 0;
+            self.credit = self.credit.checked_add(credit).ok_or_else(overflow)?;
+        }
+        Ok(())
+    }
+
+    fn is_zero(&self) -> bool {
+        self.debit == 0 && self.credit == 0
+    }
+}
+
+fn overflow() -> ProcessEvent {
+    ProcessEvent::ExternalErr("book balance limit exceeded".to_owned())
+}
+
+/// Double entry books kept underneath the client accounts.
+///
+/// Every event which moves money posts a balanced [`Posting`], so
+/// the debit balances of all book accounts always equal the
+/// credit balances.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Books {
+    system: [Balance; 3],
+    // indexed by client id, like the account table.
+    clients: Vec<Option<Balance>>,
+    postings: u64,
+}
+
+impl Books {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn balance_mut(&mut self, account: BookAccount) -> &mut Balance {
+        match account {
+            BookAccount::ExternalFunding => &mut self.system[0],
+            BookAccount::DisputeSuspense => &mut self.system[1],
+            BookAccount::ChargebackLosses => &mut self.system[2],
+            BookAccount::Client(client) => {
+                let index = client as usize;
+                if index >= self.clients.len() {
+                    self.clients.resize(index + 1, None);
+                }
+                self.clients[index].get_or_insert_with(Balance::default)
+            }
+        }
+    }
+
+    /// Post a balanced entry.
+    ///
+    /// Fails without posting either side if a balance would
+    /// exceed u128::MAX.
+    pub fn post(&mut self, posting: &Posting) -> Result<(), ProcessEvent> {
+        if !self.can_post(posting) {
+            return Err(overflow());
+        }
+        self.balance_mut(posting.debit).post_debit(posting.amount)?;
+        self.balance_mut(posting.credit)
+            .post_credit(posting.amount)?;
+        self.postings += 1;
+        Ok(())
+    }
+
+    /// Whether `posting` can be posted without a balance
+    /// exceeding u128::MAX.
+    pub fn can_post(&self, posting: &Posting) -> bool {
+        let mut debit = self.balance(posting.debit);
+        let mut credit = self.balance(posting.credit);
+        debit.post_debit(posting.amount).is_ok() && credit.post_credit(posting.amount).is_ok()
+    }
+
+    /// The balance of a book account, zero if nothing was posted to it.
+    pub fn balance(&self, account: BookAccount) -> Balance {
+        match account {
+            BookAccount::ExternalFunding => self.system[0],
+            BookAccount::DisputeSuspense => self.system[1],
+            BookAccount::ChargebackLosses => self.system[2],
+            BookAccount::Client(client) => self
+                .clients
+                .get(client as usize)
+                .copied()
+                .flatten()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Number of entries posted.
+    pub fn postings(&self) -> u64 {
+        self.postings
+    }
+
+    /// Every book account which has been posted to, system
+    /// accounts first then clients in id order.
+    pub fn iter(&self) -> impl Iterator<Item = (BookAccount, Balance)> + '_ {
+        let system = BookAccount::SYSTEM
+            .into_iter()
+            .zip(self.system)
+            .filter(|(_, balance)| !balance.is_zero());
+        let clients = self
+            .clients
+            .iter()
+            .enumerate()
+            .filter_map(|(client, balance)| {
+                Some((BookAccount::Client(client as u16), (*balance)?))
+            });
+        system.chain(clients)
+    }
+
+    /// Sum the debit and credit balances of every account.
+    pub fn trial_balance(&self) -> Result<TrialBalance, ProcessEvent> {
+        let mut trial_balance = TrialBalance {
+            rows: Vec::new(),
+            total_debit: 0,
+            total_credit: 0,
+        };
+        for (account, balance) in self.iter() {
+            trial_balance.total_debit = trial_balance
+                .total_debit
+                .checked_add(balance.debit)
+                .ok_or_else(overflow)?;
+            trial_balance.total_credit = trial_balance
+                .total_credit
+                .checked_add(balance.credit)
+                .ok_or_else(overflow)?;
+            trial_balance.rows.push((account, balance));
+        }
+        Ok(trial_balance)
+    }
+
+    /// Combine books which each hold a disjoint set of clients.
+    pub(crate) fn merge(&mut self, other: Books) -> Result<(), ProcessEvent> {
+        for (account, balance) in other.iter() {
+            let merged = self.balance_mut(account);
+            merged.post_debit(balance.debit)?;
+            merged.post_credit(balance.credit)?;
+        }
+        self.postings += other.postings;
+        Ok(())
+    }
+}
+
+/// The balance of every book account, and their totals.
+///
+/// The books balance when the total of the debit balances
+/// equals the total of the credit balances.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrialBalance {
+    pub rows: Vec<(BookAccount, Balance)>,
+    pub total_debit: u128,
+    pub total_credit: u128,
+}
+
+impl TrialBalance {
+    pub fn is_balanced(&self) -> bool {
+        self.total_debit == self.total_credit
+    }
+}
+
+impl Display for TrialBalance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let amount = |amount: u128| match amount {
+            0 => Ok(String::new()),
+            amount => Txn::u128_to_decimal_str(amount).map_err(|_| std::fmt::Error),
+        };
+        writeln!(f, "trial balance")?;
+        writeln!(f, "  {: <20}{: >20}{: >20}", "account", "debit", "credit")?;
+        for (account, balance) in &self.rows {
+            writeln!(
+                f,
+                "  {: <20}{: >20}{: >20}",
+                account.to_string(),
+                amount(balance.debit)?,
+                amount(balance.credit)?
+            )?;
+        }
+        write!(
+            f,
+            "  {: <20}{: >20}{: >20}",
+            "total",
+            Txn::u128_to_decimal_str(self.total_debit).map_err(|_| std::fmt::Error)?,
+            Txn::u128_to_decimal_str(self.total_credit).map_err(|_| std::fmt::Error)?
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{events::ProcessEvent, ledger::Ledger, transaction::Txn};
+
+    use super::{Balance, BookAccount};
+
+    #[test]
+    fn test_books_balance() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::builder().disputes_against_withdrawals(true).build();
+
+        let txns = [
+            Txn::Deposit {
+                client_id: 1,
+                txn_id: 1,
+                amount: 10_0000,
+            },
+            Txn::Deposit {
+                client_id: 2,
+                txn_id: 2,
+                amount: 5_0000,
+            },
+            Txn::Withdraw {
+                client_id: 2,
+                txn_id: 3,
+                amount: 2_0000,
+            },
+            Txn::Dispute {
+                client_id: 1,
+                txn_id: 1,
+            },
+            Txn::ChargeBack {
+                client_id: 1,
+                txn_id: 1,
+            },
+            Txn::Dispute {
+                client_id: 2,
+                txn_id: 3,
+            },
+        ];
+        for txn in txns {
+            ledger.process_txn(txn)?;
+        }
+
+        let books = ledger.books();
+        assert_eq!(books.postings(), 6);
+
+        // client books mirror the available balances
+        for (client_id, account) in ledger.accounts().iter() {
+            let balance = books.balance(BookAccount::Client(client_id));
+            assert_eq!(balance.credit, account.available);
+            assert_eq!(balance.debit, 0);
+        }
+        // and the suspense account the held balances
+        let held: u128 = ledger.accounts().iter().map(|(_, a)| a.held).sum();
+        assert_eq!(books.balance(BookAccount::DisputeSuspense).credit, held);
+
+        assert_eq!(
+            books.balance(BookAccount::ExternalFunding),
+            Balance {
+                debit: 13_0000,
+                credit: 0
+            }
+        );
+        assert_eq!(
+            books.balance(BookAccount::ChargebackLosses),
+            Balance {
+                debit: 0,
+                credit: 8_0000
+            }
+        );
+
+        let trial_balance = books.trial_balance()?;
+        assert!(trial_balance.is_balanced());
+        assert_eq!(trial_balance.total_debit, 13_0000);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    decision_log::DecisionLog,
+    events::ProcessEvent,
+    ledger::{DisputeWindow, Ledger},
+    metrics::Metrics,
+    observer::LedgerObserver,
+    policy::{PolicyLimits, TxnPolicy},
+};
+
+/// The policies a ledger applies when processing transactions.
+///
+/// The defaults are the assumptions listed in the README. A config
+/// can be loaded from a json file, in which missing fields take
+/// their default, e.g.
+///
+/// ```json
+/// { "cross_client_disputes": false, "dispute_window": { "transactions": 1000 } }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LedgerConfig {
+    /// deposits to a frozen account are ignored.
+    pub frozen_ignores_deposits: bool,
+    /// withdrawals from a frozen account are ignored.
+    pub frozen_ignores_withdrawals: bool,
+    /// withdrawals can be disputed as well as deposits.
+    pub disputes_against_withdrawals: bool,
+    /// a client can dispute, resolve or charge back
+    /// another client's transaction.
+    pub cross_client_disputes: bool,
+    /// how long a transaction can be disputed.
+    pub dispute_window: DisputeWindow,
+    /// limits enforced by the built-in policies.
+    pub limits: PolicyLimits,
+}
+
+impl Default for LedgerConfig {
+    fn default() -> Self {
+        Self {
+            frozen_ignores_deposits: true,
+            frozen_ignores_withdrawals: true,
+            disputes_against_withdrawals: false,
+            cross_client_disputes: true,
+            dispute_window: DisputeWindow::Unbounded,
+            limits: PolicyLimits::default(),
+        }
+    }
+}
+
+impl LedgerConfig {
+    /// Load a config from a json file.
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, ProcessEvent> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("failed to open {}: {err}", path.display()))
+        })?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("invalid config {}: {err}", path.display()))
+        })
+    }
+}
+
+/// Builds a [`Ledger`], one policy at a time.
+///
+/// ```
+/// use toy_txn_engine::{DisputeWindow, Ledger};
+///
+/// let ledger = Ledger::builder()
+///     .cross_client_disputes(false)
+///     .dispute_window(DisputeWindow::Transactions(1000))
+///     .build();
+/// assert!(!ledger.config().cross_client_disputes);
+/// ```
+#[derive(Default)]
+pub struct LedgerBuilder {
+    config: LedgerConfig,
+    policies: Vec<Box<dyn TxnPolicy>>,
+    observers: Vec<Box<dyn LedgerObserver>>,
+    metrics: Option<Metrics>,
+    decision_log: Option<DecisionLog>,
+}
+
+impl LedgerBuilder {
+    /// A builder starting from the default policies.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A builder starting from an existing config.
+    pub fn from_config(config: LedgerConfig) -> Self {
+        Self {
+            config,
+            policies: Vec::new(),
+            observers: Vec::new(),
+            metrics: None,
+            decision_log: None,
+        }
+    }
+
+    pub fn frozen_ignores_deposits(mut self, ignore: bool) -> Self {
+        self.config.frozen_ignores_deposits = ignore;
+        self
+    }
+
+    pub fn frozen_ignores_withdrawals(mut self, ignore: bool) -> Self {
+        self.config.frozen_ignores_withdrawals = ignore;
+        self
+    }
+
+    pub fn disputes_against_withdrawals(mut self, allow: bool) -> Self {
+        self.config.disputes_against_withdrawals = allow;
+        self
+    }
+
+    pub fn cross_client_disputes(mut self, allow: bool) -> Self {
+        self.config.cross_client_disputes = allow;
+        self
+    }
+
+    pub fn dispute_window(mut self, dispute_window: DisputeWindow) -> Self {
+        self.config.dispute_window = dispute_window;
+        self
+    }
+
+    pub fn limits(mut self, limits: PolicyLimits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    /// Add a policy to the end of the chain, after
+    /// the built-in policies set by the limits.
+    pub fn policy(mut self, policy: impl TxnPolicy + 'static) -> Self {
+        self.policies.push(Box::new(policy));
+        self
+    }
+
+    /// Send every domain event to `observer`.
+    pub fn observer(mut self, observer: impl LedgerObserver + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Record metrics of every transaction processed in `metrics`.
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Log the decision made on every transaction to `log`.
+    pub fn decision_log(mut self, log: DecisionLog) -> Self {
+        self.decision_log = Some(log);
+        self
+    }
+
+    pub fn build(self) -> Ledger {
+        let mut ledger = Ledger::with_config(self.config);
+        ledger.policies.extend(self.policies);
+        ledger.observers.extend(self.observers);
+        ledger.metrics = self.metrics;
+        ledger.decision_log = self.decision_log;
+        ledger
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::ledger::DisputeWindow;
+
+    use super::LedgerConfig;
+
+    #[test]
+    fn test_config_from_json() {
+        let config: LedgerConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, LedgerConfig::default());
+
+        let raw = r#"{
+            "frozen_ignores_withdrawals": false,
+            "disputes_against_withdrawals": true,
+            "dispute_window": { "age_secs": 90 }
+        }"#;
+        let config: LedgerConfig = serde_json::from_str(raw).unwrap();
+        assert!(config.frozen_ignores_deposits);
+        assert!(!config.frozen_ignores_withdrawals);
+        assert!(config.disputes_against_withdrawals);
+        assert!(config.cross_client_disputes);
+        assert_eq!(
+            config.dispute_window,
+            DisputeWindow::Age(Duration::from_secs(90))
+        );
+
+        let raw = r#"{ "dispute_window": { "transactions": 5 } }"#;
+        let config: LedgerConfig = serde_json::from_str(raw).unwrap();
+        assert_eq!(config.dispute_window, DisputeWindow::Transactions(5));
+
+        let raw = r#"{ "dispute_window": "unbounded" }"#;
+        assert!(serde_json::from_str::<LedgerConfig>(raw).is_ok());
+
+        // typos are not silently ignored
+        let raw = r#"{ "cross_client_dispute": false }"#;
+        assert!(serde_json::from_str::<LedgerConfig>(raw).is_err());
+
+        // the config round trips, as echoed in the run summary
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<LedgerConfig>(&json).unwrap(), config);
+    }
+}
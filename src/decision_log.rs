@@ -0,0 +1,243 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+use crate::{events::ProcessEvent, transaction::Txn};
+
+/// How much the decision log records, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    /// transactions which stopped processing.
+    Error,
+    /// transactions applied but flagged by a policy.
+    Warn,
+    /// transactions rejected, and why.
+    Info,
+    /// transactions applied.
+    Debug,
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Warn => write!(f, "warn"),
+            Level::Info => write!(f, "info"),
+            Level::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = ProcessEvent;
+
+    fn from_str(s: &str) -> Result<Self, ProcessEvent> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(ProcessEvent::ExternalErr(format!("unknown log level {s}"))),
+        }
+    }
+}
+
+/// How log entries are written, one per line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// `level key=value ...`
+    Text,
+    /// a json object.
+    Json,
+}
+
+/// What the ledger decided to do with a transaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Decision {
+    pub level: Level,
+    /// position of the transaction in the input.
+    pub seq: u64,
+    pub client: u16,
+    pub tx: u32,
+    #[serde(rename = "type")]
+    pub r#type: &'static str,
+    /// `applied`, `flagged`, `rejected` or `error`.
+    pub decision: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Decision {
+    pub fn new(seq: u64, txn: &Txn, outcome: &Result<ProcessEvent, ProcessEvent>) -> Self {
+        let (level, decision, reason) = match outcome {
+            Ok(ProcessEvent::ProcessComplete) => (Level::Debug, "applied", None),
+            Ok(ProcessEvent::Flagged(reason)) => (Level::Warn, "flagged", Some(reason.clone())),
+            Ok(ProcessEvent::Rejected(reason)) => {
+                (Level::Info, "rejected", Some(reason.to_string()))
+            }
+            Ok(ProcessEvent::ExternalErr(err)) | Err(ProcessEvent::ExternalErr(err)) => {
+                (Level::Error, "error", Some(err.clone()))
+            }
+            Err(err) => (Level::Error, "error", Some(err.to_string())),
+        };
+        Self {
+            level,
+            seq,
+            client: txn.client_id(),
+            tx: txn.txn_id(),
+            r#type: txn.type_name(),
+            decision,
+            reason,
+        }
+    }
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{: <5} seq={} client={} tx={} type={} decision={}",
+            self.level.to_string(),
+            self.seq,
+            self.client,
+            self.tx,
+            self.r#type,
+            self.decision
+        )?;
+        if let Some(reason) = &self.reason {
+            write!(f, " reason={reason:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A log of the decisions a ledger makes, at or below a level.
+///
+/// Clones write to the same output, so the ledgers of a
+/// sharded run can share a log.
+#[derive(Clone)]
+pub struct DecisionLog {
+    level: Level,
+    format: LogFormat,
+    out: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl DecisionLog {
+    pub fn new(level: Level, format: LogFormat, out: impl Write + Send + 'static) -> Self {
+        Self {
+            level,
+            format,
+            out: Arc::new(Mutex::new(out)),
+        }
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    /// Write a decision, if the log records its level.
+    pub fn record(&self, decision: &Decision) -> Result<(), ProcessEvent> {
+        if decision.level > self.level {
+            return Ok(());
+        }
+        let line = match self.format {
+            LogFormat::Text => decision.to_string(),
+            LogFormat::Json => serde_json::to_string(decision)
+                .map_err(|err| ProcessEvent::ExternalErr(err.to_string()))?,
+        };
+        let mut out = self
+            .out
+            .lock()
+            .map_err(|_| ProcessEvent::ExternalErr("decision log poisoned".to_owned()))?;
+        writeln!(out, "{line}").map_err(|err| ProcessEvent::ExternalErr(err.to_string()))
+    }
+
+    /// Flush the output.
+    pub fn flush(&self) -> Result<(), ProcessEvent> {
+        let mut out = self
+            .out
+            .lock()
+            .map_err(|_| ProcessEvent::ExternalErr("decision log poisoned".to_owned()))?;
+        out.flush()
+            .map_err(|err| ProcessEvent::ExternalErr(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use crate::{events::ProcessEvent, ledger::Ledger, transaction::Txn};
+
+    use super::{DecisionLog, Level, LogFormat};
+
+    /// a writer the test can read back.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(level: Level, format: LogFormat) -> Result<String, ProcessEvent> {
+        let out = Shared::default();
+        let log = DecisionLog::new(level, format, out.clone());
+        let mut ledger = Ledger::builder().decision_log(log).build();
+        let txns = [
+            Txn::Deposit {
+                client_id: 1,
+                txn_id: 1,
+                amount: 10_0000,
+            },
+            Txn::Withdraw {
+                client_id: 1,
+                txn_id: 2,
+                amount: 20_0000,
+            },
+            Txn::Resolve {
+                client_id: 1,
+                txn_id: 1,
+            },
+        ];
+        for txn in txns {
+            ledger.process_txn(txn)?;
+        }
+        let logged = out.0.lock().unwrap().clone();
+        Ok(String::from_utf8(logged).unwrap())
+    }
+
+    #[test]
+    fn test_decision_log() -> Result<(), ProcessEvent> {
+        assert_eq!(
+            run(Level::Info, LogFormat::Text)?,
+            r#"info  seq=2 client=1 tx=2 type=withdrawal decision=rejected reason="insufficient funds"
+info  seq=3 client=1 tx=1 type=resolve decision=rejected reason="referenced txn not in dispute"
+"#
+        );
+        assert_eq!(run(Level::Warn, LogFormat::Text)?, "");
+
+        let json = run(Level::Debug, LogFormat::Json)?;
+        let lines: Vec<serde_json::Value> = json
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["level"], "debug");
+        assert_eq!(lines[0]["decision"], "applied");
+        assert!(lines[0].get("reason").is_none());
+        assert_eq!(lines[1]["reason"], "insufficient funds");
+
+        Ok(())
+    }
+}
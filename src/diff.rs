@@ -0,0 +1,368 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{
+    events::ProcessEvent,
+    reconcile::{read_accounts, AccountRow},
+    record::{decimal, format_amount},
+    snapshot::{AccountSnapshot, Snapshot},
+};
+
+/// The state of an account to compare, from an
+/// accounts csv or a snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountState {
+    pub client: u16,
+    pub available: u128,
+    pub held: u128,
+    pub total: u128,
+    pub locked: bool,
+    /// the open disputes, only known from a snapshot.
+    pub disputes: Option<Vec<u32>>,
+}
+
+impl From<&AccountRow> for AccountState {
+    fn from(row: &AccountRow) -> Self {
+        Self {
+            client: row.client,
+            available: row.available,
+            held: row.held,
+            total: row.total,
+            locked: row.locked,
+            disputes: None,
+        }
+    }
+}
+
+impl TryFrom<&AccountSnapshot> for AccountState {
+    type Error = ProcessEvent;
+
+    fn try_from(account: &AccountSnapshot) -> Result<Self, ProcessEvent> {
+        let total = account.available.checked_add(account.held).ok_or_else(|| {
+            ProcessEvent::ExternalErr(format!("client {} total overflows", account.client))
+        })?;
+        Ok(Self {
+            client: account.client,
+            available: account.available,
+            held: account.held,
+            total,
+            locked: account.frozen,
+            disputes: Some(account.disputes.clone()),
+        })
+    }
+}
+
+/// Read the accounts from a file, a snapshot if it
+/// ends in `.json` or an accounts csv otherwise.
+pub fn read_states(path: impl AsRef<Path>) -> Result<Vec<AccountState>, ProcessEvent> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let snapshot = Snapshot::from_json_file(path)?;
+        return snapshot.accounts.iter().map(TryFrom::try_from).collect();
+    }
+    let file = File::open(path).map_err(|err| {
+        ProcessEvent::ExternalErr(format!("failed to open {}: {err}", path.display()))
+    })?;
+    let rows = read_accounts(BufReader::new(file))?;
+    Ok(rows.iter().map(AccountState::from).collect())
+}
+
+/// A balance before and after.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Delta {
+    #[serde(with = "decimal")]
+    pub before: u128,
+    #[serde(with = "decimal")]
+    pub after: u128,
+}
+
+impl Delta {
+    fn new(before: u128, after: u128) -> Option<Self> {
+        (before != after).then_some(Self { before, after })
+    }
+}
+
+impl Display for Delta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.after > self.before { '+' } else { '-' };
+        write!(
+            f,
+            "{} -> {} ({sign}{})",
+            format_amount(self.before),
+            format_amount(self.after),
+            format_amount(self.before.abs_diff(self.after))
+        )
+    }
+}
+
+/// How an account present on both sides changed.
+/// Unchanged parts are `None` or empty.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountChange {
+    pub client: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available: Option<Delta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub held: Option<Delta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<Delta>,
+    /// the lock state after, if it changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked: Option<bool>,
+    /// disputes open only after.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disputes_opened: Vec<u32>,
+    /// disputes open only before.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disputes_closed: Vec<u32>,
+}
+
+impl AccountChange {
+    fn new(before: &AccountState, after: &AccountState) -> Option<Self> {
+        let (disputes_opened, disputes_closed) = match (&before.disputes, &after.disputes) {
+            (Some(before), Some(after)) => (
+                after
+                    .iter()
+                    .filter(|tx| !before.contains(tx))
+                    .copied()
+                    .collect(),
+                before
+                    .iter()
+                    .filter(|tx| !after.contains(tx))
+                    .copied()
+                    .collect(),
+            ),
+            _ => (Vec::new(), Vec::new()),
+        };
+        let change = Self {
+            client: before.client,
+            available: Delta::new(before.available, after.available),
+            held: Delta::new(before.held, after.held),
+            total: Delta::new(before.total, after.total),
+            locked: (before.locked != after.locked).then_some(after.locked),
+            disputes_opened,
+            disputes_closed,
+        };
+        let changed = change.available.is_some()
+            || change.held.is_some()
+            || change.total.is_some()
+            || change.locked.is_some()
+            || !change.disputes_opened.is_empty()
+            || !change.disputes_closed.is_empty();
+        changed.then_some(change)
+    }
+}
+
+impl Display for AccountChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "~ client {}", self.client)?;
+        let balances = [
+            ("available", self.available),
+            ("held", self.held),
+            ("total", self.total),
+        ];
+        for (name, delta) in balances {
+            if let Some(delta) = delta {
+                write!(f, "\n    {name}: {delta}")?;
+            }
+        }
+        match self.locked {
+            Some(true) => write!(f, "\n    locked")?,
+            Some(false) => write!(f, "\n    unlocked")?,
+            None => {}
+        }
+        let ids = |txns: &[u32]| {
+            txns.iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if !self.disputes_opened.is_empty() {
+            write!(f, "\n    disputes opened: {}", ids(&self.disputes_opened))?;
+        }
+        if !self.disputes_closed.is_empty() {
+            write!(f, "\n    disputes closed: {}", ids(&self.disputes_closed))?;
+        }
+        Ok(())
+    }
+}
+
+/// The differences between two sets of accounts, each in client order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AccountsDiff {
+    /// clients only after.
+    pub added: Vec<u16>,
+    /// clients only before.
+    pub removed: Vec<u16>,
+    pub changed: Vec<AccountChange>,
+}
+
+impl AccountsDiff {
+    /// Compare the accounts `before` with the accounts `after`.
+    ///
+    /// Disputes are only compared when both sides know them,
+    /// an accounts csv does not list them. A client listed more
+    /// than once on either side is an error.
+    pub fn new(before: &[AccountState], after: &[AccountState]) -> Result<Self, ProcessEvent> {
+        let before = by_client(before, "the accounts before")?;
+        let after = by_client(after, "the accounts after")?;
+
+        let mut diff = Self::default();
+        for (client, state) in &before {
+            match after.get(client) {
+                Some(after) => diff.changed.extend(AccountChange::new(state, after)),
+                None => diff.removed.push(*client),
+            }
+        }
+        diff.added = after
+            .keys()
+            .filter(|client| !before.contains_key(client))
+            .copied()
+            .collect();
+        Ok(diff)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// The accounts of one side of a diff, by client.
+fn by_client<'a>(
+    states: &'a [AccountState],
+    side: &str,
+) -> Result<BTreeMap<u16, &'a AccountState>, ProcessEvent> {
+    let mut accounts = BTreeMap::new();
+    for state in states {
+        if accounts.insert(state.client, state).is_some() {
+            return Err(ProcessEvent::ExternalErr(format!(
+                "client {} is listed more than once in {side}",
+                state.client
+            )));
+        }
+    }
+    Ok(accounts)
+}
+
+impl Display for AccountsDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "accounts are identical");
+        }
+        // one entry per client, in client order.
+        let mut entries = Vec::new();
+        entries.extend(self.added.iter().map(|&c| (c, format!("+ client {c}"))));
+        entries.extend(self.removed.iter().map(|&c| (c, format!("- client {c}"))));
+        entries.extend(
+            self.changed
+                .iter()
+                .map(|change| (change.client, change.to_string())),
+        );
+        entries.sort_by_key(|(client, _)| *client);
+        let entries: Vec<_> = entries.into_iter().map(|(_, entry)| entry).collect();
+        write!(f, "{}", entries.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diff::AccountState, events::ProcessEvent, ledger::Ledger, reconcile::read_accounts,
+        transaction::Txn,
+    };
+
+    use super::{AccountChange, AccountsDiff, Delta};
+
+    fn states(ledger: &Ledger) -> Result<Vec<AccountState>, ProcessEvent> {
+        let snapshot = ledger.snapshot(0);
+        snapshot.accounts.iter().map(TryFrom::try_from).collect()
+    }
+
+    #[test]
+    fn test_diff() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::new();
+        let deposit = |client_id, txn_id| Txn::Deposit {
+            client_id,
+            txn_id,
+            amount: 5_0000,
+        };
+        ledger.process_txn(deposit(1, 1))?;
+        ledger.process_txn(deposit(2, 2))?;
+        ledger.process_txn(deposit(2, 3))?;
+        ledger.process_txn(Txn::Dispute {
+            client_id: 2,
+            txn_id: 2,
+        })?;
+        let before = states(&ledger)?;
+
+        ledger.process_txn(deposit(3, 4))?;
+        ledger.process_txn(Txn::Dispute {
+            client_id: 2,
+            txn_id: 3,
+        })?;
+        ledger.process_txn(Txn::ChargeBack {
+            client_id: 2,
+            txn_id: 2,
+        })?;
+        let after = states(&ledger)?;
+
+        let diff = AccountsDiff::new(&before, &after)?;
+        assert_eq!(diff.added, vec![3]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(
+            diff.changed,
+            vec![AccountChange {
+                client: 2,
+                available: Some(Delta {
+                    before: 5_0000,
+                    after: 0
+                }),
+                held: None,
+                total: Some(Delta {
+                    before: 10_0000,
+                    after: 5_0000
+                }),
+                locked: Some(true),
+                disputes_opened: vec![3],
+                disputes_closed: vec![2],
+            }]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "~ client 2
+    available: 5.0000 -> 0.0000 (-5.0000)
+    total: 10.0000 -> 5.0000 (-5.0000)
+    locked
+    disputes opened: 3
+    disputes closed: 2
++ client 3"
+        );
+
+        // accounts csvs have no disputes to compare
+        let mut printed = Vec::new();
+        ledger.write_accounts(&mut printed)?;
+        let rows = read_accounts(printed.as_slice())?;
+        let csv: Vec<_> = rows.iter().map(AccountState::from).collect();
+        assert!(AccountsDiff::new(&after, &csv)?.is_empty());
+        let diff = AccountsDiff::new(&before, &csv)?;
+        assert!(diff.changed[0].disputes_opened.is_empty());
+
+        // a client listed twice can not be compared
+        let mut twice = after.clone();
+        twice.push(after[0].clone());
+        assert_eq!(
+            AccountsDiff::new(&before, &twice),
+            Err(ProcessEvent::ExternalErr(
+                "client 1 is listed more than once in the accounts after".to_owned()
+            ))
+        );
+        assert!(AccountsDiff::new(&twice, &after).is_err());
+
+        Ok(())
+    }
+}
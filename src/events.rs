@@ -1,18 +1,62 @@
 use std::fmt::Display;
-// errors which occur during processing
+
+use serde::{Deserialize, Serialize};
+/// The outcome of processing, and errors which occur during processing.
 #[derive(Debug, PartialEq, Clone)]
 pub enum ProcessEvent {
     ProcessComplete,
+    Rejected(RejectReason),
+    /// the transaction was applied, but a policy flagged it.
+    Flagged(String),
     ExternalErr(String),
 }
 
+/// Reasons a single transaction was not applied.
+///
+/// A rejection does not stop processing of the rest of the file.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    AccountFrozen,
+    InsufficientFunds,
+    LimitExceeded,
+    TxnNotFound,
+    TxnExpired,
+    NotDisputable,
+    NotDisputed,
+    ClientMismatch,
+    /// the txn id is already in the history.
+    DuplicateTxn,
+    /// a `TxnPolicy` rejected the transaction.
+    Policy(String),
+}
+
 impl Display for ProcessEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ProcessEvent::ProcessComplete => write!(f, "",),
+            ProcessEvent::Rejected(reason) => write!(f, "rejected: {reason}"),
+            ProcessEvent::Flagged(reason) => write!(f, "flagged: {reason}"),
             ProcessEvent::ExternalErr(err) => write!(f, "{err}"),
         }
     }
 }
 
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::AccountFrozen => write!(f, "account frozen"),
+            RejectReason::InsufficientFunds => write!(f, "insufficient funds"),
+            RejectReason::LimitExceeded => write!(f, "limit exceeded"),
+            RejectReason::TxnNotFound => write!(f, "referenced txn not found"),
+            RejectReason::TxnExpired => write!(f, "referenced txn outside dispute window"),
+            RejectReason::NotDisputable => write!(f, "referenced txn cannot be disputed"),
+            RejectReason::NotDisputed => write!(f, "referenced txn not in dispute"),
+            RejectReason::ClientMismatch => write!(f, "referenced txn belongs to another client"),
+            RejectReason::DuplicateTxn => write!(f, "duplicate txn id"),
+            RejectReason::Policy(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
 impl std::error::Error for ProcessEvent {}
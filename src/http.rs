@@ -0,0 +1,356 @@
+//! A local http api over a shared ledger.
+//!
+//! | method | path                 | response                                      |
+//! |--------|----------------------|-----------------------------------------------|
+//! | `POST` | `/transactions`      | the outcome of a json record, or of an array  |
+//! | `GET`  | `/transactions/{tx}` | the client, type, amount and dispute state    |
+//! | `GET`  | `/accounts`          | every account, as json                        |
+//! | `GET`  | `/accounts/{client}` | one account, as json                          |
+//! | `GET`  | `/accounts.csv`      | every account in the csv output format        |
+//!
+//! Records are json objects with the fields of the csv, amounts as
+//! strings: `{"type":"deposit","client":1,"tx":1,"amount":"1.5"}`.
+//! Errors are json objects with an `error` field, with a 4xx status
+//! for a bad request and 5xx when the ledger failed. A batch answers
+//! each record in turn, an invalid record with an error in its place,
+//! and a ledger failure stops it with a 5xx.
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::ProcessEvent,
+    ledger::Ledger,
+    reconcile::AccountRow,
+    record::{decimal, Record},
+    transaction::{Txn, TxnKind, TxnState},
+};
+
+/// the largest request body accepted, in bytes.
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// One record, or a batch applied in order.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Submission {
+    One(Record),
+    Batch(Vec<Record>),
+}
+
+/// The reply to each record of a batch.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum BatchReply {
+    Outcome(OutcomeReply),
+    Error(ErrorReply),
+}
+
+/// The outcome of a record, as a json reply.
+#[derive(Serialize)]
+struct OutcomeReply {
+    outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ErrorReply {
+    error: String,
+}
+
+/// A transaction in the history, as a json reply.
+#[derive(Serialize)]
+struct TxnReply {
+    tx: u32,
+    client: u16,
+    #[serde(rename = "type")]
+    kind: TxnKind,
+    #[serde(with = "decimal")]
+    amount: u128,
+    state: TxnState,
+}
+
+/// A response, before it is written.
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: &impl Serialize) -> Self {
+        match serde_json::to_string(body) {
+            Ok(body) => Self {
+                status,
+                content_type: "application/json",
+                body,
+            },
+            Err(err) => Self::error(500, err.to_string()),
+        }
+    }
+
+    fn error(status: u16, error: impl Into<String>) -> Self {
+        let body = serde_json::to_string(&ErrorReply {
+            error: error.into(),
+        })
+        .unwrap_or_default();
+        Self {
+            status,
+            content_type: "application/json",
+            body,
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Serves the http api over a ledger shared by every connection.
+pub struct HttpServer {
+    listener: TcpListener,
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl HttpServer {
+    pub fn bind(addr: impl ToSocketAddrs, ledger: Ledger) -> Result<Self, ProcessEvent> {
+        let listener = TcpListener::bind(addr).map_err(io_err)?;
+        Ok(Self {
+            listener,
+            ledger: Arc::new(Mutex::new(ledger)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, ProcessEvent> {
+        self.listener.local_addr().map_err(io_err)
+    }
+
+    /// The ledger the api is served over.
+    pub fn ledger(&self) -> Arc<Mutex<Ledger>> {
+        self.ledger.clone()
+    }
+
+    /// Accept connections until the listener fails, answering one
+    /// request per connection, each on its own thread.
+    pub fn run(self) -> Result<(), ProcessEvent> {
+        for stream in self.listener.incoming() {
+            let stream = stream.map_err(io_err)?;
+            let ledger = self.ledger.clone();
+            thread::spawn(move || serve_connection(stream, &ledger));
+        }
+        Ok(())
+    }
+}
+
+fn io_err(err: std::io::Error) -> ProcessEvent {
+    ProcessEvent::ExternalErr(err.to_string())
+}
+
+fn serve_connection(mut stream: TcpStream, ledger: &Mutex<Ledger>) {
+    let response = match read_request(&mut BufReader::new(&mut stream)) {
+        Ok((method, path, body)) => route(ledger, &method, &path, &body),
+        Err(response) => response,
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    // the client may be gone, there is no one to report to.
+    let _ = stream.write_all((head + &response.body).as_bytes());
+}
+
+/// Read the method, path and body of a request.
+fn read_request(reader: &mut impl BufRead) -> Result<(String, String, Vec<u8>), Response> {
+    let bad_request = |error: &str| Response::error(400, error);
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .map_err(|_| bad_request("unreadable request"))?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(bad_request("invalid request line"));
+    };
+    let (method, path) = (method.to_owned(), path.to_owned());
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader
+            .read_line(&mut line)
+            .map_err(|_| bad_request("unreadable headers"))?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| bad_request("invalid content-length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(Response::error(413, "request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| bad_request("request body shorter than its content-length"))?;
+    Ok((method, path, body))
+}
+
+fn route(ledger: &Mutex<Ledger>, method: &str, path: &str, body: &[u8]) -> Response {
+    let path = path.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        ("POST", ["transactions"]) => submit(ledger, body),
+        ("GET", ["transactions", tx]) => match tx.parse() {
+            Ok(tx) => transaction(ledger, tx),
+            Err(_) => Response::error(400, format!("invalid transaction id {tx}")),
+        },
+        ("GET", ["accounts"]) => accounts(ledger),
+        ("GET", ["accounts.csv"]) => accounts_csv(ledger),
+        ("GET", ["accounts", client]) => match client.parse() {
+            Ok(client) => account(ledger, client),
+            Err(_) => Response::error(400, format!("invalid client {client}")),
+        },
+        (_, ["transactions" | "accounts" | "accounts.csv"] | ["transactions" | "accounts", _]) => {
+            Response::error(405, format!("{method} is not allowed on {path}"))
+        }
+        _ => Response::error(404, format!("no such path {path}")),
+    }
+}
+
+fn lock(ledger: &Mutex<Ledger>) -> Result<MutexGuard<'_, Ledger>, Response> {
+    ledger
+        .lock()
+        .map_err(|_| Response::error(500, "ledger poisoned"))
+}
+
+fn submit(ledger: &Mutex<Ledger>, body: &[u8]) -> Response {
+    match serde_json::from_slice(body) {
+        Ok(Submission::One(record)) => match apply(ledger, record) {
+            Ok(BatchReply::Outcome(reply)) => Response::json(200, &reply),
+            Ok(BatchReply::Error(reply)) => Response::json(400, &reply),
+            Err(err) => Response::error(500, err.to_string()),
+        },
+        Ok(Submission::Batch(records)) => {
+            let total = records.len();
+            let mut replies = Vec::with_capacity(total);
+            for record in records {
+                match apply(ledger, record) {
+                    Ok(reply) => replies.push(reply),
+                    Err(err) => {
+                        let applied = replies.len();
+                        let error = format!("{err}, after {applied} of {total} records");
+                        return Response::error(500, error);
+                    }
+                }
+            }
+            Response::json(200, &replies)
+        }
+        Err(err) => Response::error(400, format!("invalid record: {err}")),
+    }
+}
+
+/// Apply a record, answering an invalid record with an error
+/// reply. An error is a failure of the ledger.
+fn apply(ledger: &Mutex<Ledger>, record: Record) -> Result<BatchReply, ProcessEvent> {
+    let txn = match Txn::from_record(record) {
+        Ok(txn) => txn,
+        Err(err) => {
+            return Ok(BatchReply::Error(ErrorReply {
+                error: err.to_string(),
+            }))
+        }
+    };
+    let mut ledger = ledger
+        .lock()
+        .map_err(|_| ProcessEvent::ExternalErr("ledger poisoned".to_owned()))?;
+    let (outcome, reason) = match ledger.process_txn(txn)? {
+        ProcessEvent::ProcessComplete => ("applied", None),
+        ProcessEvent::Flagged(reason) => ("flagged", Some(reason)),
+        ProcessEvent::Rejected(reason) => ("rejected", Some(reason.to_string())),
+        err @ ProcessEvent::ExternalErr(_) => return Err(err),
+    };
+    Ok(BatchReply::Outcome(OutcomeReply { outcome, reason }))
+}
+
+fn transaction(ledger: &Mutex<Ledger>, tx: u32) -> Response {
+    let ledger = match lock(ledger) {
+        Ok(ledger) => ledger,
+        Err(response) => return response,
+    };
+    match ledger.txn(tx) {
+        Some(txn) => Response::json(
+            200,
+            &TxnReply {
+                tx,
+                client: txn.client_id,
+                kind: txn.kind,
+                amount: txn.amount(),
+                state: txn.state,
+            },
+        ),
+        None => Response::error(404, format!("transaction {tx} is not in the history")),
+    }
+}
+
+fn account(ledger: &Mutex<Ledger>, client: u16) -> Response {
+    let ledger = match lock(ledger) {
+        Ok(ledger) => ledger,
+        Err(response) => return response,
+    };
+    match ledger.account(client).map(|a| AccountRow::new(client, a)) {
+        Some(Ok(row)) => Response::json(200, &row),
+        Some(Err(err)) => Response::error(500, err.to_string()),
+        None => Response::error(404, format!("no account for client {client}")),
+    }
+}
+
+fn accounts(ledger: &Mutex<Ledger>) -> Response {
+    let ledger = match lock(ledger) {
+        Ok(ledger) => ledger,
+        Err(response) => return response,
+    };
+    let rows: Result<Vec<_>, _> = ledger
+        .accounts()
+        .iter()
+        .map(|(client, account)| AccountRow::new(client, account))
+        .collect();
+    match rows {
+        Ok(rows) => Response::json(200, &rows),
+        Err(err) => Response::error(500, err.to_string()),
+    }
+}
+
+fn accounts_csv(ledger: &Mutex<Ledger>) -> Response {
+    let ledger = match lock(ledger) {
+        Ok(ledger) => ledger,
+        Err(response) => return response,
+    };
+    let mut out = Vec::new();
+    if let Err(err) = ledger.write_accounts(&mut out) {
+        return Response::error(500, err.to_string());
+    }
+    Response {
+        status: 200,
+        content_type: "text/csv",
+        body: String::from_utf8_lossy(&out).into_owned(),
+    }
+}
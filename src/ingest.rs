@@ -0,0 +1,277 @@
+//! Async ingestion of records into a ledger, for tokio based services.
+//!
+//! [`Ingest`] is a stream of the outcomes of records read from another
+//! stream. It reads a record only when the next outcome is asked for, so
+//! a slow consumer holds back the input rather than records piling up.
+//! Records can come from any stream, or from csv or json lines read with
+//! [`csv_records`] and [`jsonl_records`].
+//!
+//! ```no_run
+//! # async fn run(input: impl tokio::io::AsyncRead) {
+//! use tokio_stream::StreamExt;
+//! use tokio_util::sync::CancellationToken;
+//! use toy_txn_engine::{ingest::{csv_records, Ingest}, Ledger};
+//!
+//! let cancel = CancellationToken::new();
+//! let mut ingest = Ingest::new(Ledger::new(), csv_records(input), cancel.clone());
+//! while let Some(outcome) = ingest.next().await {
+//!     println!("{:?}", outcome);
+//! }
+//! let ledger = ingest.into_ledger();
+//! # }
+//! ```
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use csv::StringRecord;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio_stream::{wrappers::LinesStream, Stream, StreamExt};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+use crate::{events::ProcessEvent, ledger::Ledger, record::Record, transaction::Txn};
+
+/// A transaction the ledger processed, and what it decided.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    /// position of the transaction in the input.
+    pub seq: u64,
+    pub txn: Txn,
+    pub event: ProcessEvent,
+}
+
+/// The outcomes of applying a stream of records to a ledger.
+///
+/// Each record is applied in full within a single poll, so the ledger
+/// holds exactly the records whose outcomes have been yielded. Once the
+/// token is cancelled the stream ends before the next record, and
+/// [`Ingest::into_ledger`] gives back the ledger as of that point. A
+/// record which can not be read or applied ends the stream with its
+/// error, as it ends the processing of a csv file.
+pub struct Ingest<S> {
+    ledger: Ledger,
+    records: Pin<Box<S>>,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+    done: bool,
+}
+
+impl<S: Stream<Item = Result<Record, ProcessEvent>>> Ingest<S> {
+    pub fn new(ledger: Ledger, records: S, cancel: CancellationToken) -> Self {
+        Self {
+            ledger,
+            records: Box::pin(records),
+            cancelled: Box::pin(cancel.cancelled_owned()),
+            done: false,
+        }
+    }
+
+    /// The ledger, with the records yielded so far applied.
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn into_ledger(self) -> Ledger {
+        self.ledger
+    }
+}
+
+impl<S: Stream<Item = Result<Record, ProcessEvent>>> Stream for Ingest<S> {
+    type Item = Result<Outcome, ProcessEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        // polled first, so a cancelled ingest stops even while it
+        // waits for a record.
+        if this.cancelled.as_mut().poll(cx).is_ready() {
+            this.done = true;
+            return Poll::Ready(None);
+        }
+        let record = match ready!(this.records.as_mut().poll_next(cx)) {
+            Some(Ok(record)) => record,
+            Some(Err(err)) => {
+                this.done = true;
+                return Poll::Ready(Some(Err(err)));
+            }
+            None => {
+                this.done = true;
+                return Poll::Ready(None);
+            }
+        };
+        let outcome = Txn::from_record(record).and_then(|txn| {
+            let event = this.ledger.process_txn(txn.clone())?;
+            Ok(Outcome {
+                seq: this.ledger.seq(),
+                txn,
+                event,
+            })
+        });
+        this.done = outcome.is_err();
+        Poll::Ready(Some(outcome))
+    }
+}
+
+/// The non blank lines of `reader`.
+fn lines<R: AsyncRead>(reader: R) -> impl Stream<Item = Result<String, ProcessEvent>> {
+    LinesStream::new(BufReader::new(reader).lines()).filter_map(|line| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(Ok(line)),
+        Err(err) => Some(Err(ProcessEvent::ExternalErr(err.to_string()))),
+    })
+}
+
+/// Parse a line of csv into its fields.
+fn csv_row(line: &str) -> Result<StringRecord, ProcessEvent> {
+    csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(line.as_bytes())
+        .records()
+        .next()
+        .unwrap_or_else(|| Ok(StringRecord::new()))
+        .map_err(|err| ProcessEvent::ExternalErr(format!("invalid record: {err}")))
+}
+
+/// Records read from a transactions csv, with a header, one per line.
+pub fn csv_records<R: AsyncRead>(reader: R) -> impl Stream<Item = Result<Record, ProcessEvent>> {
+    let mut headers = None;
+    lines(reader).filter_map(move |line| {
+        let row = line.and_then(|line| csv_row(&line));
+        let row = match (row, &headers) {
+            (Ok(row), None) => {
+                headers = Some(row);
+                return None;
+            }
+            (Ok(row), Some(headers)) => row
+                .deserialize(Some(headers))
+                .map_err(|err| ProcessEvent::ExternalErr(format!("invalid record: {err}"))),
+            (Err(err), _) => Err(err),
+        };
+        Some(row)
+    })
+}
+
+/// Records read from json lines, one object per line with the
+/// fields of the csv.
+pub fn jsonl_records<R: AsyncRead>(reader: R) -> impl Stream<Item = Result<Record, ProcessEvent>> {
+    lines(reader).map(|line| {
+        line.and_then(|line| {
+            serde_json::from_str(&line)
+                .map_err(|err| ProcessEvent::ExternalErr(format!("invalid record: {err}")))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio_stream::StreamExt;
+    use tokio_util::sync::CancellationToken;
+
+    use crate::{
+        events::{ProcessEvent, RejectReason},
+        ledger::Ledger,
+        record::Record,
+    };
+
+    use super::{csv_records, jsonl_records, Ingest};
+
+    fn deposit(client: u16, tx: u32) -> Record {
+        Record {
+            r#type: "deposit".to_owned(),
+            client,
+            tx,
+            amount: Some(1_0000),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ingest() -> Result<(), ProcessEvent> {
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 2.0
+
+withdrawal, 1, 2, 5.0
+dispute, 1, 1,
+refund, 1, 1,
+deposit, 1, 3, 1.0
+";
+        let mut ingest = Ingest::new(
+            Ledger::new(),
+            csv_records(csv.as_bytes()),
+            CancellationToken::new(),
+        );
+        let mut outcomes = Vec::new();
+        while let Some(outcome) = ingest.next().await {
+            outcomes.push(outcome);
+        }
+        assert_eq!(outcomes.len(), 4);
+        assert_eq!(
+            outcomes[0].as_ref().unwrap().event,
+            ProcessEvent::ProcessComplete
+        );
+        assert_eq!(
+            outcomes[1].as_ref().unwrap().event,
+            ProcessEvent::Rejected(RejectReason::InsufficientFunds)
+        );
+        assert_eq!(outcomes[2].as_ref().unwrap().seq, 3);
+        // an unknown record ends the stream
+        assert!(outcomes[3].is_err());
+        assert_eq!(ingest.ledger().account(1).unwrap().held, 2_0000);
+
+        let jsonl = r#"{"type":"deposit","client":2,"tx":1,"amount":"1.5"}
+{"type":"withdrawal","client":2,"tx":2,"amount":"0.5"}
+"#;
+        let ingest = Ingest::new(
+            Ledger::new(),
+            jsonl_records(jsonl.as_bytes()),
+            CancellationToken::new(),
+        );
+        let outcomes: Vec<_> = ingest.collect().await;
+        assert!(outcomes.iter().all(Result::is_ok));
+
+        // records are only read as outcomes are asked for
+        let read = Arc::new(AtomicUsize::new(0));
+        let records = {
+            let read = read.clone();
+            tokio_stream::iter((1..=100).map(|tx| deposit(1, tx))).map(move |record| {
+                read.fetch_add(1, Ordering::SeqCst);
+                Ok(record)
+            })
+        };
+        let mut ingest = Ingest::new(Ledger::new(), records, CancellationToken::new());
+        ingest.next().await.unwrap()?;
+        ingest.next().await.unwrap()?;
+        assert_eq!(read.load(Ordering::SeqCst), 2);
+
+        // cancelling while waiting for a record ends the stream, with
+        // the records before it applied
+        let cancel = CancellationToken::new();
+        let records = tokio_stream::iter((1..=3).map(|tx| Ok(deposit(tx as u16, tx))))
+            .chain(tokio_stream::pending());
+        let mut ingest = Ingest::new(Ledger::new(), records, cancel.clone());
+        for _ in 0..3 {
+            ingest.next().await.unwrap()?;
+        }
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            cancel.cancel();
+        });
+        assert!(ingest.next().await.is_none());
+        assert!(ingest.next().await.is_none());
+
+        let ledger = ingest.into_ledger();
+        let mut expected = Ledger::new();
+        for tx in 1..=3 {
+            expected.process_transaction(deposit(tx as u16, tx))?;
+        }
+        assert_eq!(ledger.seq(), 3);
+        assert_eq!(ledger.state_hash(), expected.state_hash());
+
+        Ok(())
+    }
+}
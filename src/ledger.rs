@@ -1,180 +1,927 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use crate::{account::Account, events::ProcessEvent, record::Record, transaction::Txn};
+use serde::{Deserialize, Serialize};
 
+use crate::{
+    account::{Account, AccountTable},
+    books::{Books, Posting},
+    config::{LedgerBuilder, LedgerConfig},
+    decision_log::{Decision, DecisionLog},
+    events::{ProcessEvent, RejectReason},
+    merkle::{Hash, MerkleTree},
+    metrics::{Change, Metrics},
+    observer::{DomainEvent, EventRecord, LedgerObserver},
+    policy::{PolicyContext, PolicyDecision, TxnPolicy},
+    record::Record,
+    snapshot::{AccountSnapshot, HistorySnapshot, Snapshot},
+    summary::LedgerStats,
+    transaction::{DisputableTxn, Txn, TxnIds, TxnKind, TxnState},
+};
+
+/// How long a transaction stays in the transaction
+/// history, and so how long it can be disputed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeWindow {
+    /// history is kept for the lifetime of the ledger.
+    Unbounded,
+    /// history is kept for this many subsequent transactions.
+    Transactions(u64),
+    /// history is kept for this long after it was recorded.
+    #[serde(rename = "age_secs", with = "duration_secs")]
+    Age(Duration),
+}
+
+// (de)serialise a duration as whole seconds.
+mod duration_secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+/// Client accounts, and the history of deposits which can still be disputed.
+///
+/// Transactions are applied one at a time, in input order, with
+/// [`Ledger::process_transaction`] or [`Ledger::process_txn`].
 pub struct Ledger {
-    pub accounts: HashMap<u16, Account>,
-    pub txn_history: HashMap<u32, Txn>,
+    pub(crate) accounts: AccountTable,
+    pub(crate) txn_history: HashMap<u32, DisputableTxn>,
+    books: Books,
+    config: LedgerConfig,
+    pub(crate) policies: Vec<Box<dyn TxnPolicy>>,
+    pub(crate) observers: Vec<Box<dyn LedgerObserver>>,
+    pub(crate) metrics: Option<Metrics>,
+    pub(crate) decision_log: Option<DecisionLog>,
+    // events of the transaction being processed, sent
+    // to the observers once it has been processed.
+    pending: Vec<DomainEvent>,
+    // history in the order it was recorded, oldest first,
+    // so expired entries can be evicted from the front.
+    history_order: VecDeque<(u64, Instant, u32)>,
+    // ids of evicted txns, kept so disputes against them can be
+    // told apart from disputes against txns that never existed.
+    expired: HashSet<u32>,
+    // ids of withdrawals, and of rejected deposits, which are not
+    // kept in the history, so they can not be reused.
+    spent_ids: TxnIds,
+    stats: LedgerStats,
+    seq: u64,
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Ledger {
+    /// An empty ledger which keeps its history for its whole lifetime.
     pub fn new() -> Self {
+        Self::with_dispute_window(DisputeWindow::Unbounded)
+    }
+
+    /// An empty ledger which evicts history outside `dispute_window`.
+    pub fn with_dispute_window(dispute_window: DisputeWindow) -> Self {
+        Self::builder().dispute_window(dispute_window).build()
+    }
+
+    /// A builder for a ledger with non default policies.
+    pub fn builder() -> LedgerBuilder {
+        LedgerBuilder::new()
+    }
+
+    /// An empty ledger applying the policies in `config`.
+    pub fn with_config(config: LedgerConfig) -> Self {
         Self {
-            accounts: HashMap::new(),
+            accounts: AccountTable::new(),
             txn_history: HashMap::new(),
+            books: Books::new(),
+            policies: config.limits.policies(),
+            observers: Vec::new(),
+            metrics: None,
+            decision_log: None,
+            pending: Vec::new(),
+            config,
+            history_order: VecDeque::new(),
+            expired: HashSet::new(),
+            spent_ids: TxnIds::default(),
+            stats: LedgerStats::default(),
+            seq: 0,
         }
     }
 
-    fn txn_from_history(&self, txn_id: u32) -> Option<&Txn> {
+    /// Send every domain event to `observer` from now on.
+    pub fn subscribe(&mut self, observer: impl LedgerObserver + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// The account for `client_id`, if it has been opened.
+    pub fn account(&self, client_id: u16) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    /// Every open account, in client id order.
+    pub fn accounts(&self) -> &AccountTable {
+        &self.accounts
+    }
+
+    /// The history entry for a deposit, if it can still be referenced.
+    pub fn txn(&self, txn_id: u32) -> Option<&DisputableTxn> {
         self.txn_history.get(&txn_id)
     }
+
+    /// Position in the input of the last transaction processed.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Number of deposits currently kept in the history.
+    pub fn history_len(&self) -> usize {
+        self.txn_history.len()
+    }
+
+    /// The double entry books underneath the accounts.
+    pub fn books(&self) -> &Books {
+        &self.books
+    }
+
+    /// Counts of the transactions processed.
+    pub fn stats(&self) -> &LedgerStats {
+        &self.stats
+    }
+
+    /// The policies the ledger was built with.
+    pub fn config(&self) -> &LedgerConfig {
+        &self.config
+    }
+
+    fn is_expired(&self, seq: u64, recorded_at: Instant) -> bool {
+        match self.config.dispute_window {
+            DisputeWindow::Unbounded => false,
+            DisputeWindow::Transactions(window) => self.seq - seq > window,
+            DisputeWindow::Age(window) => recorded_at.elapsed() >= window,
+        }
+    }
+
+    /// remember an applied transaction so it can be disputed later.
+    fn record_history(&mut self, kind: TxnKind, client_id: u16, txn_id: u32, amount: u128) {
+        if self.config.dispute_window != DisputeWindow::Unbounded {
+            self.history_order
+                .push_back((self.seq, Instant::now(), txn_id));
+        }
+        self.txn_history.insert(
+            txn_id,
+            DisputableTxn::new(kind, client_id, amount, self.seq),
+        );
+    }
+
+    /// Expire history entries which have left the dispute window.
+    fn evict_expired(&mut self) {
+        while let Some(&(seq, recorded_at, txn_id)) = self.history_order.front() {
+            if !self.is_expired(seq, recorded_at) {
+                break;
+            }
+            self.history_order.pop_front();
+
+            // the id may have been reused by a later txn.
+            let Some(entry) = self.txn_history.get(&txn_id) else {
+                continue;
+            };
+            if entry.seq != seq {
+                continue;
+            }
+            let event = DomainEvent::Expired {
+                client: entry.client_id,
+                tx: txn_id,
+            };
+            self.expire(txn_id);
+            self.pending.push(event);
+        }
+    }
+
+    /// Drop a history entry which has left the dispute window.
+    ///
+    /// Entries under dispute are only marked as expired and kept
+    /// until they are resolved or charged back, so held funds
+    /// can always be released.
+    fn expire(&mut self, txn_id: u32) {
+        let Some(entry) = self.txn_history.get_mut(&txn_id) else {
+            return;
+        };
+        if entry.state == TxnState::Disputed {
+            entry.expired = true;
+            return;
+        }
+        self.txn_history.remove(&txn_id);
+        self.expired.insert(txn_id);
+    }
+
+    /// Remove a settled entry if it outlived the dispute window
+    /// while it was under dispute.
+    fn release_settled(&mut self, txn_id: u32) {
+        if self
+            .txn_history
+            .get(&txn_id)
+            .is_some_and(|entry| entry.expired)
+        {
+            self.txn_history.remove(&txn_id);
+            self.expired.insert(txn_id);
+        }
+    }
+
+    /// Whether a deposit or withdrawal has already used `txn_id`,
+    /// whether or not it was applied or is still in the history.
+    fn is_duplicate(&self, txn_id: u32) -> bool {
+        self.txn_history.contains_key(&txn_id)
+            || self.expired.contains(&txn_id)
+            || self.spent_ids.contains(txn_id)
+    }
+
+    /// Spend `txn_id` for a transaction the ledger did not see, as a
+    /// shard does for an id first used on another shard.
+    pub(crate) fn spend_txn_id(&mut self, txn_id: u32) {
+        if !self.is_duplicate(txn_id) {
+            self.spent_ids.insert(txn_id);
+        }
+    }
+
+    fn missing_txn(&self, txn_id: u32) -> ProcessEvent {
+        if self.expired.contains(&txn_id) {
+            ProcessEvent::Rejected(RejectReason::TxnExpired)
+        } else {
+            ProcessEvent::Rejected(RejectReason::TxnNotFound)
+        }
+    }
+
+    /// Record an event and fold it into the ledger.
+    fn emit(&mut self, event: DomainEvent) -> Result<(), ProcessEvent> {
+        self.apply(&event)?;
+        self.pending.push(event);
+        Ok(())
+    }
+
+    /// Fold a single event into the accounts and history.
+    ///
+    /// This is the only place account balances change, so a ledger
+    /// rebuilt from its events is identical to the original.
+    ///
+    /// Every change is checked before any is made, so an event which
+    /// can not be applied leaves the ledger as it was.
+    fn apply(&mut self, event: &DomainEvent) -> Result<(), ProcessEvent> {
+        let changed = self.changed_account(event)?;
+        if let DomainEvent::DisputeOpened { tx, .. }
+        | DomainEvent::Resolved { tx, .. }
+        | DomainEvent::ChargedBack { tx, .. } = *event
+        {
+            self.history_entry(tx)?;
+        }
+
+        // posting fails without a change, and nothing after it can fail.
+        if let Some(posting) = Posting::for_event(event) {
+            self.books.post(&posting)?;
+        }
+        if let Some((client, account)) = changed {
+            *self.accounts.get_or_insert(client) = account;
+        }
+        match *event {
+            DomainEvent::Deposited { client, tx, amount } => {
+                // only applied transactions can be disputed.
+                self.record_history(TxnKind::Deposit, client, tx, amount);
+            }
+            DomainEvent::Withdrew { client, tx, amount } => {
+                if self.config.disputes_against_withdrawals {
+                    self.record_history(TxnKind::Withdrawal, client, tx, amount);
+                } else {
+                    self.spent_ids.insert(tx);
+                }
+            }
+            DomainEvent::DisputeOpened { tx, .. } => {
+                self.history_entry(tx)?.state = TxnState::Disputed;
+            }
+            DomainEvent::Resolved { tx, .. } => {
+                self.history_entry(tx)?.state = TxnState::Settled;
+                self.release_settled(tx);
+            }
+            DomainEvent::ChargedBack { tx, .. } => {
+                self.history_entry(tx)?.state = TxnState::ChargedBack;
+                self.release_settled(tx);
+            }
+            DomainEvent::Expired { tx, .. } => self.expire(tx),
+            // an id is used by its first deposit or withdrawal, even
+            // if it is rejected, so a retry is a duplicate.
+            DomainEvent::Rejected { tx, ref r#type, .. } => {
+                if matches!(r#type.as_str(), "deposit" | "withdrawal") && !self.is_duplicate(tx) {
+                    self.spent_ids.insert(tx);
+                }
+            }
+            DomainEvent::AccountOpened { .. } | DomainEvent::AccountFrozen { .. } => {}
+        }
+        self.stats.record(event);
+        self.stats.peak_history = self.stats.peak_history.max(self.txn_history.len());
+        Ok(())
+    }
+
+    /// The account an event changes, as it is after the event,
+    /// worked out on a copy of the account.
+    fn changed_account(&self, event: &DomainEvent) -> Result<Option<(u16, Account)>, ProcessEvent> {
+        if let DomainEvent::Expired { .. } | DomainEvent::Rejected { .. } = event {
+            return Ok(None);
+        }
+        let client = event.client();
+        let mut account = self.accounts.get(&client).cloned().unwrap_or_default();
+        match *event {
+            DomainEvent::Deposited { amount, .. } => account.add_available(amount)?,
+            DomainEvent::Withdrew { amount, .. } => account.sub_available(amount)?,
+            DomainEvent::DisputeOpened {
+                tx, kind, amount, ..
+            } => {
+                if kind == TxnKind::Deposit {
+                    account.sub_available(amount)?;
+                }
+                account.add_held(amount)?;
+                account.disputes.insert(tx);
+            }
+            DomainEvent::Resolved {
+                tx, kind, amount, ..
+            } => {
+                account.sub_held(amount)?;
+                if kind == TxnKind::Deposit {
+                    account.add_available(amount)?;
+                }
+                account.disputes.remove(&tx);
+            }
+            DomainEvent::ChargedBack {
+                tx, kind, amount, ..
+            } => {
+                account.sub_held(amount)?;
+                if kind == TxnKind::Withdrawal {
+                    account.add_available(amount)?;
+                }
+                account.disputes.remove(&tx);
+            }
+            DomainEvent::AccountFrozen { .. } => account.freeze(),
+            DomainEvent::AccountOpened { .. }
+            | DomainEvent::Expired { .. }
+            | DomainEvent::Rejected { .. } => {}
+        }
+        Ok(Some((client, account)))
+    }
+
+    fn history_entry(&mut self, txn_id: u32) -> Result<&mut DisputableTxn, ProcessEvent> {
+        self.txn_history
+            .get_mut(&txn_id)
+            .ok_or_else(|| ProcessEvent::ExternalErr(format!("txn {txn_id} not in history")))
+    }
+
+    /// The account for `client_id`, opening it on its first transaction.
+    fn open_account(&mut self, client_id: u16) -> Result<&Account, ProcessEvent> {
+        if !self.accounts.contains_key(&client_id) {
+            self.emit(DomainEvent::AccountOpened { client: client_id })?;
+        }
+        Ok(self.accounts.get_or_insert(client_id))
+    }
+
     /// Deposit to available balance.
     ///
-    /// Will fail if available balance exceeds u128::MAX.
+    /// Will fail if the total of the account would exceed u128::MAX.
     ///
-    /// Will fail if the account is frozen.
+    /// Will fail if the account is frozen, unless
+    /// `frozen_ignores_deposits` is off.
+    ///
+    /// Will fail if the txn id has already been used.
     ///
     /// If the deposit fails the app will
     /// continue to process other transactions.
-    fn deposit(&mut self, txn: Txn) -> Result<(), ProcessEvent> {
-        let account = self
-            .accounts
-            .entry(txn.client_id())
-            .or_insert(Account::new());
+    fn deposit(&mut self, txn: &Txn) -> Result<ProcessEvent, ProcessEvent> {
+        let event = DomainEvent::Deposited {
+            client: txn.client_id(),
+            tx: txn.txn_id(),
+            amount: txn.amount(),
+        };
+        // funding every account adds up in the books.
+        let fundable = Posting::for_event(&event).is_some_and(|p| self.books.can_post(&p));
+        let frozen_ignores_deposits = self.config.frozen_ignores_deposits;
+        let duplicate = self.is_duplicate(txn.txn_id());
+        let account = self.open_account(txn.client_id())?;
 
-        if account.frozen {
-            // handle account frozen
-        } else if account.add_available(txn.amount()).is_err() {
-            // handle deposit failed here
+        if account.frozen && frozen_ignores_deposits {
+            return Ok(ProcessEvent::Rejected(RejectReason::AccountFrozen));
+        }
+        if duplicate {
+            return Ok(ProcessEvent::Rejected(RejectReason::DuplicateTxn));
+        }
+        if !account.can_credit(txn.amount()) || !fundable {
+            return Ok(ProcessEvent::Rejected(RejectReason::LimitExceeded));
         }
 
-        self.txn_history.insert(txn.txn_id(), txn);
-        Ok(())
+        self.emit(event)?;
+        Ok(ProcessEvent::ProcessComplete)
     }
 
     /// Withdraw from available balance.
     ///
     /// Will fail if available balance is insufficient.
     ///
-    /// Will fail if the account is frozen.
+    /// Will fail if the account is frozen, unless
+    /// `frozen_ignores_withdrawals` is off.
+    ///
+    /// Will fail if the txn id has already been used.
     ///
     /// If the withdrawal fails the app will
     /// continue to process other transactions.
-    fn withdraw(&mut self, txn: Txn) -> Result<(), ProcessEvent> {
-        let account = self
-            .accounts
-            .entry(txn.client_id())
-            .or_insert(Account::new());
+    ///
+    /// Withdrawals are only kept in the history if
+    /// `disputes_against_withdrawals` is on.
+    fn withdraw(&mut self, txn: &Txn) -> Result<ProcessEvent, ProcessEvent> {
+        let frozen_ignores_withdrawals = self.config.frozen_ignores_withdrawals;
+        let duplicate = self.is_duplicate(txn.txn_id());
+        let account = self.open_account(txn.client_id())?;
 
-        if account.frozen {
-            // handle account frozen
-        } else if account.sub_available(txn.amount()).is_err() {
-            // handle withdrawal failed here
+        if account.frozen && frozen_ignores_withdrawals {
+            return Ok(ProcessEvent::Rejected(RejectReason::AccountFrozen));
+        }
+        if duplicate {
+            return Ok(ProcessEvent::Rejected(RejectReason::DuplicateTxn));
+        }
+        if account.available < txn.amount() {
+            return Ok(ProcessEvent::Rejected(RejectReason::InsufficientFunds));
         }
 
-        self.txn_history.insert(txn.txn_id(), txn);
-        Ok(())
+        self.emit(DomainEvent::Withdrew {
+            client: txn.client_id(),
+            tx: txn.txn_id(),
+            amount: txn.amount(),
+        })?;
+        Ok(ProcessEvent::ProcessComplete)
+    }
+
+    /// Find the history entry a dispute, resolve or chargeback refers to.
+    ///
+    /// Rejects the reference if the txn does not exist, or belongs to
+    /// another client and `cross_client_disputes` is off.
+    fn referenced(&self, txn: &Txn) -> Result<&DisputableTxn, ProcessEvent> {
+        let txn_id = txn.txn_id();
+        let Some(entry) = self.txn_history.get(&txn_id) else {
+            return Err(self.missing_txn(txn_id));
+        };
+        if !self.config.cross_client_disputes && entry.client_id != txn.client_id() {
+            return Err(ProcessEvent::Rejected(RejectReason::ClientMismatch));
+        }
+        Ok(entry)
     }
 
     /// dispute a referenced transaction.
     ///
+    /// Disputing a deposit holds the deposited funds. Disputing a
+    /// withdrawal holds the withdrawn amount, as it may be returned.
+    ///
     /// If referenced txn does not exist will ignore.
-    fn dispute(&mut self, txn: &Txn) -> Result<(), ProcessEvent> {
-        let txn_id = txn.txn_id();
+    ///
+    /// If referenced txn is outside the dispute window will ignore.
+    ///
+    /// If referenced txn is already disputed or charged back will ignore.
+    ///
+    /// If the funds of a disputed deposit are no longer available,
+    /// e.g. they have been withdrawn, will ignore.
+    ///
+    /// If holding a disputed withdrawal would take the total of the
+    /// account past u128::MAX will ignore.
+    fn dispute(&mut self, txn: &Txn) -> Result<ProcessEvent, ProcessEvent> {
         // assume partner error if txn referenced
         // does not exist and ignore.
-        if let Some(referenced_txn) = self.txn_from_history(txn_id) {
-
-            // only valid for deposits, ignore otherwise
-            if matches!(referenced_txn, Txn::Deposit { .. }) {
-                let amount = referenced_txn.amount();
-                let account = self
-                    .accounts
-                    .entry(referenced_txn.client_id())
-                    .or_insert(Account::new());
-    
-                account.sub_available(amount)?;
-                account.add_held(amount)?;
-                account.disputes.insert(txn_id);
-            }
+        let entry = match self.referenced(txn) {
+            Ok(entry) => entry,
+            Err(rejected) => return Ok(rejected),
+        };
+        if entry.expired {
+            return Ok(ProcessEvent::Rejected(RejectReason::TxnExpired));
         }
-        Ok(())
+        if entry.state != TxnState::Settled {
+            return Ok(ProcessEvent::Rejected(RejectReason::NotDisputable));
+        }
+        // holding a disputed deposit takes its funds from available.
+        if entry.kind == TxnKind::Deposit
+            && self
+                .accounts
+                .get(&entry.client_id)
+                .is_none_or(|account| account.available < entry.amount())
+        {
+            return Ok(ProcessEvent::Rejected(RejectReason::InsufficientFunds));
+        }
+        // holding a disputed withdrawal adds to the total.
+        if entry.kind == TxnKind::Withdrawal
+            && !self
+                .accounts
+                .get(&entry.client_id)
+                .is_some_and(|account| account.can_credit(entry.amount()))
+        {
+            return Ok(ProcessEvent::Rejected(RejectReason::LimitExceeded));
+        }
+
+        self.emit(DomainEvent::DisputeOpened {
+            client: entry.client_id,
+            tx: txn.txn_id(),
+            kind: entry.kind,
+            amount: entry.amount(),
+        })?;
+        Ok(ProcessEvent::ProcessComplete)
     }
 
-    /// resolve a referenced transaction.
+    /// resolve a referenced transaction, releasing held funds.
+    ///
+    /// Resolving a deposit returns the funds to the available
+    /// balance. Resolving a withdrawal lets the withdrawal stand.
     ///
     /// If referenced txn does not exist will ignore.
     ///
     /// If referenced is not in dispute will ignore.
-    fn resolve(&mut self, txn: &Txn) -> Result<(), ProcessEvent> {
-        let txn_id = txn.txn_id();
-
+    fn resolve(&mut self, txn: &Txn) -> Result<ProcessEvent, ProcessEvent> {
         // assume partner error if txn referenced
         // does not exist, or txn not disputed and ignore.
-        if let Some(referenced_txn) = self.txn_from_history(txn_id) {
-            let amount = referenced_txn.amount();
-            let account = self
-                .accounts
-                .entry(referenced_txn.client_id())
-                .or_insert(Account::new());
-
-            if account.disputes.contains(&txn_id) {
-                account.sub_held(amount)?;
-                account.add_available(amount)?;
-                account.disputes.remove(&txn_id);
-            }
+        let entry = match self.referenced(txn) {
+            Ok(entry) => entry,
+            Err(rejected) => return Ok(rejected),
+        };
+        if entry.state != TxnState::Disputed {
+            return Ok(ProcessEvent::Rejected(RejectReason::NotDisputed));
         }
-        Ok(())
+
+        self.emit(DomainEvent::Resolved {
+            client: entry.client_id,
+            tx: txn.txn_id(),
+            kind: entry.kind,
+            amount: entry.amount(),
+        })?;
+        Ok(ProcessEvent::ProcessComplete)
     }
 
-    /// chargeback a referenced transaction.
+    /// chargeback a referenced transaction, reversing it and
+    /// freezing the account.
+    ///
+    /// Charging back a deposit removes the held funds. Charging
+    /// back a withdrawal returns the held amount to the account.
     ///
     /// If referenced txn does not exist will ignore.
     ///
     /// If referenced is not in dispute will ignore.
-    fn chargeback(&mut self, txn: &Txn) -> Result<(), ProcessEvent> {
-        let txn_id = txn.txn_id();
-
+    fn chargeback(&mut self, txn: &Txn) -> Result<ProcessEvent, ProcessEvent> {
         // assume partner error if txn referenced
         // does not exist, or txn not disputed and ignore.
-        if let Some(referenced_txn) = self.txn_from_history(txn_id) {
-            let amount = referenced_txn.amount();
-            let account = self
-                .accounts
-                .entry(referenced_txn.client_id())
-                .or_insert(Account::new());
+        let entry = match self.referenced(txn) {
+            Ok(entry) => entry,
+            Err(rejected) => return Ok(rejected),
+        };
+        if entry.state != TxnState::Disputed {
+            return Ok(ProcessEvent::Rejected(RejectReason::NotDisputed));
+        }
 
-            if account.disputes.contains(&txn_id) {
-                account.sub_held(amount)?;
-                account.disputes.remove(&txn_id);
-                account.freeze();
+        let client = entry.client_id;
+        let was_frozen = self.accounts.get(&client).is_some_and(|a| a.frozen);
+        self.emit(DomainEvent::ChargedBack {
+            client,
+            tx: txn.txn_id(),
+            kind: entry.kind,
+            amount: entry.amount(),
+        })?;
+        if !was_frozen {
+            self.emit(DomainEvent::AccountFrozen { client })?;
+        }
+        Ok(ProcessEvent::ProcessComplete)
+    }
+
+    /// Run the policy chain, then apply the transaction if approved.
+    fn add_tx_to_account(&mut self, txn: Txn) -> Result<ProcessEvent, ProcessEvent> {
+        let context = PolicyContext {
+            seq: self.seq,
+            account: self.accounts.get(&txn.client_id()),
+        };
+        let mut flags = Vec::new();
+        let mut rejected = None;
+        for policy in self.policies.iter_mut() {
+            match policy.evaluate(&txn, &context) {
+                PolicyDecision::Approve => {}
+                PolicyDecision::Reject(reason) => {
+                    rejected = Some(ProcessEvent::Rejected(RejectReason::Policy(reason)));
+                    break;
+                }
+                PolicyDecision::Flag(reason) => flags.push(reason),
             }
         }
 
-        Ok(())
-    }
+        let (client, tx, r#type) = (txn.client_id(), txn.txn_id(), txn.type_name());
+        let event = match (rejected, &txn) {
+            (Some(rejected), _) => rejected,
+            (None, Txn::Deposit { .. }) => self.deposit(&txn)?,
+            (None, Txn::Withdraw { .. }) => self.withdraw(&txn)?,
+            (None, Txn::Dispute { .. }) => self.dispute(&txn)?,
+            (None, Txn::Resolve { .. }) => self.resolve(&txn)?,
+            (None, Txn::ChargeBack { .. }) => self.chargeback(&txn)?,
+        };
 
-    fn add_tx_to_account(&mut self, txn: Txn) -> Result<(), ProcessEvent> {
-        match txn {
-            Txn::Deposit { .. } => self.deposit(txn)?,
-            Txn::Withdraw { .. } => self.withdraw(txn)?,
-            Txn::Dispute { .. } => self.dispute(&txn)?,
-            Txn::Resolve { .. } => self.resolve(&txn)?,
-            Txn::ChargeBack { .. } => self.chargeback(&txn)?,
+        if event == ProcessEvent::ProcessComplete {
+            let context = PolicyContext {
+                seq: self.seq,
+                account: self.accounts.get(&client),
+            };
+            for policy in self.policies.iter_mut() {
+                policy.applied(&txn, &context);
+            }
         }
-        Ok(())
+
+        if let ProcessEvent::Rejected(reason) = &event {
+            self.emit(DomainEvent::Rejected {
+                client,
+                tx,
+                r#type: r#type.to_owned(),
+                reason: reason.clone(),
+            })?;
+        }
+        if event == ProcessEvent::ProcessComplete && !flags.is_empty() {
+            return Ok(ProcessEvent::Flagged(flags.join("; ")));
+        }
+        Ok(event)
     }
 
+    /// Apply a single record to the ledger.
+    ///
+    /// Returns `ProcessEvent::Rejected` with the reason if the
+    /// transaction was ignored, and an error if the record
+    /// is malformed.
     pub fn process_transaction(&mut self, record: Record) -> Result<ProcessEvent, ProcessEvent> {
         let txn = Txn::from_record(record)?;
-        self.add_tx_to_account(txn)?;
-        Ok(ProcessEvent::ProcessComplete)
+        self.process_txn(txn)
+    }
+
+    /// Apply an already parsed transaction to the ledger.
+    ///
+    /// Returns `ProcessEvent::Rejected` with the reason if the
+    /// transaction was ignored.
+    pub fn process_txn(&mut self, txn: Txn) -> Result<ProcessEvent, ProcessEvent> {
+        self.process_txn_at(self.seq + 1, txn)
+    }
+
+    /// Apply a transaction as the `seq`th record of the input.
+    ///
+    /// Used when the input is split over several ledgers, so the
+    /// dispute window still counts every record of the input.
+    pub(crate) fn process_txn_at(
+        &mut self,
+        seq: u64,
+        txn: Txn,
+    ) -> Result<ProcessEvent, ProcessEvent> {
+        if self.metrics.is_none() {
+            return self.process_next(seq, txn);
+        }
+        let started = Instant::now();
+        let (accounts, history) = (self.accounts.len(), self.txn_history.len());
+        let outcome = self.process_next(seq, txn.clone());
+        let change = Change {
+            accounts: self.accounts.len() as i64 - accounts as i64,
+            history: self.txn_history.len() as i64 - history as i64,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.observe(&txn, &outcome, started.elapsed(), change);
+        }
+        outcome
+    }
+
+    fn process_next(&mut self, seq: u64, txn: Txn) -> Result<ProcessEvent, ProcessEvent> {
+        self.seq = seq;
+        self.evict_expired();
+        if self.decision_log.is_none() {
+            let outcome = self.add_tx_to_account(txn);
+            self.notify_observers()?;
+            return outcome;
+        }
+        let outcome = self.add_tx_to_account(txn.clone());
+        if let Some(log) = &self.decision_log {
+            log.record(&Decision::new(seq, &txn, &outcome))?;
+        }
+        self.notify_observers()?;
+        outcome
+    }
+
+    /// deliver the events of the last transaction to every observer.
+    fn notify_observers(&mut self) -> Result<(), ProcessEvent> {
+        for event in self.pending.drain(..) {
+            for observer in self.observers.iter_mut() {
+                observer.on_event(self.seq, &event)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every account in client id order, with its disputes in id order.
+    pub fn snapshot_accounts(&self) -> Vec<AccountSnapshot> {
+        self.accounts
+            .iter()
+            .map(|(client, account)| {
+                let mut disputes: Vec<u32> = account.disputes.iter().copied().collect();
+                disputes.sort_unstable();
+                AccountSnapshot {
+                    client,
+                    available: account.available,
+                    held: account.held,
+                    disputes,
+                    frozen: account.frozen,
+                }
+            })
+            .collect()
+    }
+
+    /// A canonical hash of the accounts, the root of their [`MerkleTree`].
+    pub fn state_hash(&self) -> Hash {
+        MerkleTree::new(self).root()
+    }
+
+    /// The state of the ledger, for a journal which is
+    /// `journal_offset` bytes long at this point.
+    pub fn snapshot(&self, journal_offset: u64) -> Snapshot {
+        let accounts = self.snapshot_accounts();
+        let mut history: Vec<HistorySnapshot> = self
+            .txn_history
+            .iter()
+            .map(|(&tx, entry)| HistorySnapshot {
+                tx,
+                client: entry.client_id,
+                kind: entry.kind,
+                state: entry.state,
+                amount: entry.amount(),
+                seq: entry.seq,
+                expired: entry.expired,
+            })
+            .collect();
+        history.sort_unstable_by_key(|entry| entry.tx);
+        let mut expired: Vec<u32> = self.expired.iter().copied().collect();
+        expired.sort_unstable();
+        let spent = self.spent_ids.ids();
+
+        Snapshot {
+            seq: self.seq,
+            journal_offset,
+            config: self.config.clone(),
+            accounts,
+            history,
+            expired,
+            spent,
+            books: self.books.clone(),
+        }
+    }
+
+    /// A ledger restored from a snapshot, with the policies of its
+    /// config starting afresh.
+    ///
+    /// History entries restart their dispute window age from now.
+    pub fn from_snapshot(snapshot: Snapshot) -> Ledger {
+        let mut ledger = Ledger::with_config(snapshot.config);
+        ledger.seq = snapshot.seq;
+        ledger.books = snapshot.books;
+        for account in snapshot.accounts {
+            let restored = ledger.accounts.get_or_insert(account.client);
+            restored.available = account.available;
+            restored.held = account.held;
+            restored.disputes = account.disputes.into_iter().collect();
+            restored.frozen = account.frozen;
+        }
+        let mut history = snapshot.history;
+        history.sort_unstable_by_key(|entry| entry.seq);
+        for entry in history {
+            let mut restored =
+                DisputableTxn::new(entry.kind, entry.client, entry.amount, entry.seq);
+            restored.state = entry.state;
+            restored.expired = entry.expired;
+            if ledger.config.dispute_window != DisputeWindow::Unbounded && !entry.expired {
+                ledger
+                    .history_order
+                    .push_back((entry.seq, Instant::now(), entry.tx));
+            }
+            ledger.txn_history.insert(entry.tx, restored);
+        }
+        ledger.expired = snapshot.expired.into_iter().collect();
+        ledger.spent_ids.extend(snapshot.spent);
+        ledger
+    }
+
+    /// Fold a journalled event into the ledger, as the ledger which
+    /// wrote the journal did when it emitted the event.
+    ///
+    /// Policies are not evaluated and observers are not notified,
+    /// the event records a decision which has already been made.
+    pub fn apply_event(&mut self, record: &EventRecord) -> Result<(), ProcessEvent> {
+        // history entries record the seq of the event which made them.
+        let seq = std::mem::replace(&mut self.seq, record.seq);
+        let applied = self.apply(&record.event);
+        if applied.is_err() {
+            self.seq = seq;
+        }
+        applied
     }
 
+    /// Rebuild a ledger from a journal of events, one json
+    /// [`EventRecord`] per line, as written by `JsonlEventSink`.
+    ///
+    /// `config` should be the config the journal was written with,
+    /// as it decides which transactions are kept in the history.
+    pub fn replay<R: BufRead>(config: LedgerConfig, journal: R) -> Result<Ledger, ProcessEvent> {
+        let mut ledger = Ledger::with_config(config);
+        for (number, line) in journal.lines().enumerate() {
+            let line = line.map_err(|err| ProcessEvent::ExternalErr(err.to_string()))?;
+            let record: EventRecord = serde_json::from_str(&line).map_err(|err| {
+                ProcessEvent::ExternalErr(format!("invalid event on line {}: {err}", number + 1))
+            })?;
+            ledger.apply_event(&record)?;
+        }
+        Ok(ledger)
+    }
+
+    /// Rebuild a ledger from the journal file at `path`.
+    pub fn replay_file(
+        config: LedgerConfig,
+        path: impl AsRef<Path>,
+    ) -> Result<Ledger, ProcessEvent> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("failed to open {}: {err}", path.display()))
+        })?;
+        Self::replay(config, BufReader::new(file))
+    }
+
+    /// Combine ledgers which each hold a disjoint set of clients.
+    ///
+    /// Fails if the combined books exceed the amount limit.
+    pub fn merge(ledgers: Vec<Ledger>) -> Result<Ledger, ProcessEvent> {
+        let mut merged = Ledger::new();
+        for ledger in ledgers {
+            merged.config = ledger.config;
+            merged.seq = merged.seq.max(ledger.seq);
+            for (client_id, account) in ledger.accounts.iter() {
+                *merged.accounts.get_or_insert(client_id) = account.clone();
+            }
+            merged.txn_history.extend(ledger.txn_history);
+            merged.history_order.extend(ledger.history_order);
+            merged.expired.extend(ledger.expired);
+            merged.spent_ids.extend(ledger.spent_ids.ids());
+            merged.books.merge(ledger.books)?;
+            merged.stats.merge(&ledger.stats);
+        }
+        merged
+            .history_order
+            .make_contiguous()
+            .sort_by_key(|(seq, _, _)| *seq);
+        // shards only evict when they see a txn, so catch up
+        // to the last record of the input.
+        merged.evict_expired();
+        merged.pending.clear();
+        Ok(merged)
+    }
+
+    /// Write every account to stdout in the csv output format.
     pub fn print_accounts(&self) -> Result<(), ProcessEvent> {
-        println!(
+        self.write_accounts(&mut std::io::stdout().lock())
+    }
+
+    /// Write every account to `out` in the csv output format.
+    pub fn write_accounts<W: Write>(&self, out: &mut W) -> Result<(), ProcessEvent> {
+        self.write_accounts_filtered(out, |_| true)
+    }
+
+    /// Write the accounts of the clients `keep` returns true for
+    /// to `out` in the csv output format.
+    pub fn write_accounts_filtered<W: Write>(
+        &self,
+        out: &mut W,
+        keep: impl Fn(u16) -> bool,
+    ) -> Result<(), ProcessEvent> {
+        let io_err = |err: std::io::Error| ProcessEvent::ExternalErr(err.to_string());
+        writeln!(
+            out,
             "{: >10},{: >10},{: >10},{: >10},{: >10}",
             "client", "available", "held", "total", "locked"
-        );
-        for (key, val) in self.accounts.iter() {
+        )
+        .map_err(io_err)?;
+        for (key, val) in self.accounts.iter().filter(|(key, _)| keep(*key)) {
             let available = Txn::u128_to_decimal_str(val.available)?;
             let held = Txn::u128_to_decimal_str(val.held)?;
-            let total = Txn::u128_to_decimal_str(val.total())?;
+            let total = Txn::u128_to_decimal_str(val.total()?)?;
             let frozen = val.frozen;
-            println!(
+            writeln!(
+                out,
                 "{: >10},{: >10},{: >10},{: >10},{: >10}",
                 key, available, held, total, frozen
-            );
+            )
+            .map_err(io_err)?;
         }
         Ok(())
     }
@@ -182,9 +929,20 @@ impl Ledger {
 
 #[cfg(test)]
 mod tests {
-    use crate::{account::Account, events::ProcessEvent, ledger::Record};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use crate::{
+        account::Account,
+        events::{ProcessEvent, RejectReason},
+        ledger::Record,
+    };
 
-    use super::Ledger;
+    use super::{DisputeWindow, Ledger};
+    use crate::books::BookAccount;
+    use crate::observer::{DomainEvent, EventRecord, JsonlEventSink, LedgerObserver};
+    use crate::policy::{PolicyLimits, VelocityLimit};
+    use crate::transaction::{Txn, TxnKind, TxnState};
 
     fn record(r#type: String, client: u16, tx: u32, amount: Option<u128>) -> Record {
         Record {
@@ -326,4 +1084,490 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_dispute_window_transactions() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::with_dispute_window(DisputeWindow::Transactions(2));
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        ledger.process_transaction(record("deposit".to_owned(), 1, 2, Some(10_0000)))?;
+
+        // txn #2 was recorded 1 transaction ago
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 2, None))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+
+        // txn #1 was recorded 3 transactions ago
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::TxnExpired));
+
+        // a txn which never existed is reported differently
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 9, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::TxnNotFound));
+
+        // the disputed txn #2 outlives the window until it is settled
+        ledger.process_transaction(record("deposit".to_owned(), 1, 3, Some(10_0000)))?;
+        assert!(!ledger.txn_history.contains_key(&1));
+        assert!(ledger.txn_history.contains_key(&2));
+
+        let event = ledger.process_transaction(record("resolve".to_owned(), 1, 2, None))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+        assert!(!ledger.txn_history.contains_key(&2));
+
+        let account: &Account = ledger.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 30_0000);
+        assert_eq!(account.held, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispute_window_age() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::with_dispute_window(DisputeWindow::Age(Duration::ZERO));
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::TxnExpired));
+        assert!(ledger.txn_history.is_empty());
+
+        let mut ledger = Ledger::with_dispute_window(DisputeWindow::Age(Duration::from_secs(60)));
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejection_reasons() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::new();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        ledger.process_transaction(record("deposit".to_owned(), 1, 5, Some(5_0000)))?;
+        ledger.process_transaction(record("withdrawal".to_owned(), 1, 2, Some(5_0000)))?;
+
+        let event =
+            ledger.process_transaction(record("withdrawal".to_owned(), 1, 3, Some(50_0000)))?;
+        assert_eq!(
+            event,
+            ProcessEvent::Rejected(RejectReason::InsufficientFunds)
+        );
+
+        // withdrawals are not kept in the history
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 2, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::TxnNotFound));
+
+        let event = ledger.process_transaction(record("resolve".to_owned(), 1, 1, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::NotDisputed));
+
+        ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+
+        // a txn cannot be held twice
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::NotDisputable));
+        assert_eq!(ledger.accounts.get(&1).unwrap().held, 10_0000);
+
+        ledger.process_transaction(record("chargeback".to_owned(), 1, 1, None))?;
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::NotDisputable));
+
+        let event = ledger.process_transaction(record("deposit".to_owned(), 1, 4, Some(1_0000)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::AccountFrozen));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispute_withdrawn_deposit() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::new();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        ledger.process_transaction(record("withdrawal".to_owned(), 1, 2, Some(10_0000)))?;
+
+        // the deposited funds have gone, so there is nothing to hold
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        assert_eq!(
+            event,
+            ProcessEvent::Rejected(RejectReason::InsufficientFunds)
+        );
+        let account = ledger.accounts.get(&1).unwrap();
+        assert_eq!((account.available, account.held), (0, 0));
+
+        // and processing carries on
+        let event = ledger.process_transaction(record("deposit".to_owned(), 1, 3, Some(1_0000)))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_event_changes_nothing() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::new();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        ledger.process_transaction(record("withdrawal".to_owned(), 1, 2, Some(10_0000)))?;
+        ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        let postings = ledger.books().postings();
+
+        // holding the withdrawn deposit can not be applied, and must
+        // not leave the books posted without the account
+        let dispute = EventRecord {
+            seq: ledger.seq() + 1,
+            event: DomainEvent::DisputeOpened {
+                client: 1,
+                tx: 1,
+                kind: TxnKind::Deposit,
+                amount: 10_0000,
+            },
+        };
+        let seq = ledger.seq();
+        assert!(ledger.apply_event(&dispute).is_err());
+        assert_eq!(ledger.seq(), seq);
+
+        let account = ledger.accounts().get(&1).unwrap();
+        assert_eq!((account.available, account.held), (0, 0));
+        assert!(account.disputes.is_empty());
+        let books = ledger.books();
+        assert_eq!(books.postings(), postings);
+        let client = books.balance(BookAccount::Client(1));
+        assert_eq!((client.debit, client.credit), (0, account.available));
+        assert_eq!(
+            books.balance(BookAccount::DisputeSuspense).credit,
+            account.held
+        );
+        assert_eq!(ledger.txn(1).unwrap().state, TxnState::Settled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_txn() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::new();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+
+        // reusing the id of a disputed deposit must not reset its entry
+        let event = ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(3_0000)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::DuplicateTxn));
+        let event =
+            ledger.process_transaction(record("withdrawal".to_owned(), 1, 1, Some(1_0000)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::DuplicateTxn));
+
+        let event = ledger.process_transaction(record("resolve".to_owned(), 1, 1, None))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+        let account = ledger.accounts.get(&1).unwrap();
+        assert_eq!((account.available, account.held), (10_0000, 0));
+        assert_eq!(ledger.txn(1).unwrap().amount(), 10_0000);
+
+        // withdrawals are not kept in the history, but their ids are spent
+        ledger.process_transaction(record("withdrawal".to_owned(), 1, 2, Some(1_0000)))?;
+        let event = ledger.process_transaction(record("deposit".to_owned(), 1, 2, Some(3_0000)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::DuplicateTxn));
+        assert_eq!(ledger.accounts.get(&1).unwrap().available, 9_0000);
+
+        // so are the ids of rejected transactions
+        let event =
+            ledger.process_transaction(record("withdrawal".to_owned(), 1, 3, Some(100_0000)))?;
+        assert_eq!(
+            event,
+            ProcessEvent::Rejected(RejectReason::InsufficientFunds)
+        );
+        let event = ledger.process_transaction(record("deposit".to_owned(), 1, 3, Some(3_0000)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::DuplicateTxn));
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 3, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::TxnNotFound));
+
+        // as are the ids of deposits which left the dispute window
+        let mut ledger = Ledger::with_dispute_window(DisputeWindow::Transactions(1));
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(1_0000)))?;
+        ledger.process_transaction(record("deposit".to_owned(), 1, 2, Some(1_0000)))?;
+        ledger.process_transaction(record("deposit".to_owned(), 1, 3, Some(1_0000)))?;
+        assert!(ledger.txn(1).is_none());
+        let event = ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(9_0000)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::DuplicateTxn));
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::TxnExpired));
+        assert_eq!(ledger.accounts.get(&1).unwrap().available, 3_0000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_total_limit() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::builder().disputes_against_withdrawals(true).build();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(u128::MAX - 5)))?;
+        ledger.process_transaction(record("deposit".to_owned(), 1, 2, Some(5)))?;
+        ledger.process_transaction(record("dispute".to_owned(), 1, 2, None))?;
+
+        // available has room, but the total would pass the limit
+        let event = ledger.process_transaction(record("deposit".to_owned(), 1, 3, Some(1)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::LimitExceeded));
+        assert_eq!(ledger.accounts.get(&1).unwrap().total(), Ok(u128::MAX));
+
+        // holding a disputed withdrawal adds to the total as well
+        ledger.process_transaction(record("resolve".to_owned(), 1, 2, None))?;
+        ledger.process_transaction(record("withdrawal".to_owned(), 1, 4, Some(10)))?;
+        ledger.process_transaction(record("deposit".to_owned(), 1, 5, Some(10)))?;
+        let event = ledger.process_transaction(record("dispute".to_owned(), 1, 4, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::LimitExceeded));
+
+        // the books cannot be funded past the limit either
+        let event = ledger.process_transaction(record("deposit".to_owned(), 2, 6, Some(1)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::LimitExceeded));
+
+        // the largest total is printed exactly
+        let mut out = Vec::new();
+        ledger.write_accounts(&mut out)?;
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            "34028236692093846346337460743176821.1455,    0.0000,34028236692093846346337460743176821.1455"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frozen_policies() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::builder().frozen_ignores_deposits(false).build();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        ledger.process_transaction(record("deposit".to_owned(), 1, 2, Some(10_0000)))?;
+        ledger.process_transaction(record("dispute".to_owned(), 1, 2, None))?;
+        ledger.process_transaction(record("chargeback".to_owned(), 1, 2, None))?;
+
+        let event = ledger.process_transaction(record("deposit".to_owned(), 1, 3, Some(5_0000)))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+        let event =
+            ledger.process_transaction(record("withdrawal".to_owned(), 1, 4, Some(1_0000)))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::AccountFrozen));
+
+        let account: &Account = ledger.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 15_0000);
+        assert!(account.frozen);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_disputes_against_withdrawals() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::builder().disputes_against_withdrawals(true).build();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        ledger.process_transaction(record("withdrawal".to_owned(), 1, 2, Some(4_0000)))?;
+        ledger.process_transaction(record("withdrawal".to_owned(), 1, 3, Some(1_0000)))?;
+
+        // the withdrawn amount is held while disputed
+        ledger.process_transaction(record("dispute".to_owned(), 1, 2, None))?;
+        let account: &Account = ledger.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 5_0000);
+        assert_eq!(account.held, 4_0000);
+
+        // resolving lets the withdrawal stand
+        ledger.process_transaction(record("resolve".to_owned(), 1, 2, None))?;
+        let account: &Account = ledger.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 5_0000);
+        assert_eq!(account.held, 0);
+
+        // charging back returns the withdrawn amount
+        ledger.process_transaction(record("dispute".to_owned(), 1, 3, None))?;
+        ledger.process_transaction(record("chargeback".to_owned(), 1, 3, None))?;
+        let account: &Account = ledger.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 6_0000);
+        assert_eq!(account.held, 0);
+        assert!(account.frozen);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_client_disputes() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::builder().cross_client_disputes(false).build();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        let event = ledger.process_transaction(record("dispute".to_owned(), 2, 1, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::ClientMismatch));
+
+        ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        let event = ledger.process_transaction(record("chargeback".to_owned(), 2, 1, None))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::ClientMismatch));
+
+        let account: &Account = ledger.accounts.get(&1).unwrap();
+        assert_eq!(account.held, 10_0000);
+        assert!(!account.frozen);
+        assert!(ledger.accounts.get(&2).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_chain() -> Result<(), ProcessEvent> {
+        let limits = PolicyLimits {
+            max_withdrawal: Some(5_0000),
+            blocked_clients: vec![9],
+            deposit_velocity: Some(VelocityLimit {
+                max_deposits: 1,
+                window_txns: 10,
+            }),
+        };
+        let mut ledger = Ledger::builder().limits(limits).build();
+
+        let event =
+            ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+
+        // a second deposit within the window is applied but flagged
+        let event =
+            ledger.process_transaction(record("deposit".to_owned(), 1, 2, Some(10_0000)))?;
+        assert!(matches!(event, ProcessEvent::Flagged(_)));
+
+        let event =
+            ledger.process_transaction(record("withdrawal".to_owned(), 1, 3, Some(6_0000)))?;
+        assert_eq!(
+            event,
+            ProcessEvent::Rejected(RejectReason::Policy(
+                "withdrawal exceeds limit of 5.0000".to_owned()
+            ))
+        );
+
+        let event = ledger.process_transaction(record("deposit".to_owned(), 9, 4, Some(1_0000)))?;
+        assert!(matches!(
+            event,
+            ProcessEvent::Rejected(RejectReason::Policy(_))
+        ));
+        // policies run before the account is opened
+        assert!(ledger.accounts.get(&9).is_none());
+
+        let account: &Account = ledger.accounts.get(&1).unwrap();
+        assert_eq!(account.available, 20_0000);
+
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct EventLog(Vec<(u64, DomainEvent)>);
+
+    impl LedgerObserver for EventLog {
+        fn on_event(&mut self, seq: u64, event: &DomainEvent) -> Result<(), ProcessEvent> {
+            self.0.push((seq, event.clone()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_observer_events() -> Result<(), ProcessEvent> {
+        let log = Arc::new(Mutex::new(EventLog::default()));
+        let mut ledger = Ledger::builder().observer(log.clone()).build();
+
+        ledger.process_transaction(record("deposit".to_owned(), 1, 1, Some(10_0000)))?;
+        ledger.process_transaction(record("withdrawal".to_owned(), 1, 2, Some(50_0000)))?;
+        ledger.process_transaction(record("dispute".to_owned(), 1, 1, None))?;
+        ledger.process_transaction(record("chargeback".to_owned(), 1, 1, None))?;
+        // a frozen account ignores the deposit
+        ledger.process_transaction(record("deposit".to_owned(), 1, 3, Some(1_0000)))?;
+
+        let log = log.lock().unwrap();
+        assert_eq!(
+            log.0,
+            vec![
+                (1, DomainEvent::AccountOpened { client: 1 }),
+                (
+                    1,
+                    DomainEvent::Deposited {
+                        client: 1,
+                        tx: 1,
+                        amount: 10_0000,
+                    }
+                ),
+                (
+                    2,
+                    DomainEvent::Rejected {
+                        client: 1,
+                        tx: 2,
+                        r#type: "withdrawal".to_owned(),
+                        reason: RejectReason::InsufficientFunds,
+                    }
+                ),
+                (
+                    3,
+                    DomainEvent::DisputeOpened {
+                        client: 1,
+                        tx: 1,
+                        kind: TxnKind::Deposit,
+                        amount: 10_0000,
+                    }
+                ),
+                (
+                    4,
+                    DomainEvent::ChargedBack {
+                        client: 1,
+                        tx: 1,
+                        kind: TxnKind::Deposit,
+                        amount: 10_0000,
+                    }
+                ),
+                (4, DomainEvent::AccountFrozen { client: 1 }),
+                (
+                    5,
+                    DomainEvent::Rejected {
+                        client: 1,
+                        tx: 3,
+                        r#type: "deposit".to_owned(),
+                        reason: RejectReason::AccountFrozen,
+                    }
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_equals_live_state() -> Result<(), ProcessEvent> {
+        let journal = Arc::new(Mutex::new(JsonlEventSink::new(Vec::new())));
+        let mut ledger = Ledger::builder()
+            .dispute_window(DisputeWindow::Transactions(4))
+            .disputes_against_withdrawals(true)
+            .observer(journal.clone())
+            .build();
+
+        let records = [
+            ("deposit", 1, 1, Some(10_0000)),
+            ("deposit", 1, 2, Some(4_0000)),
+            ("deposit", 2, 3, Some(5_0000)),
+            ("dispute", 1, 1, None),
+            ("withdrawal", 1, 4, Some(2_0000)),
+            ("withdrawal", 2, 5, Some(9_0000)),
+            ("dispute", 2, 3, None),
+            ("chargeback", 2, 3, None),
+            ("deposit", 3, 6, Some(1_0000)),
+            // tx 1 outlived the window while disputed
+            ("resolve", 1, 1, None),
+            ("dispute", 1, 4, None),
+            ("deposit", 3, 7, Some(7_5000)),
+            ("withdrawal", 3, 8, Some(5000)),
+        ];
+        for (r#type, client, tx, amount) in records {
+            ledger.process_transaction(record(r#type.to_owned(), client, tx, amount))?;
+        }
+
+        let journal = journal.lock().unwrap().get_ref().clone();
+        let mut replayed = Ledger::replay(ledger.config().clone(), journal.as_slice())?;
+
+        let live: Vec<(u16, &Account)> = ledger.accounts().iter().collect();
+        let rebuilt: Vec<(u16, &Account)> = replayed.accounts().iter().collect();
+        assert_eq!(live, rebuilt);
+        assert_eq!(ledger.history_len(), replayed.history_len());
+        for txn_id in 1..=8 {
+            assert_eq!(ledger.txn(txn_id), replayed.txn(txn_id));
+        }
+        // tombstones survive the replay
+        assert_eq!(
+            replayed.process_txn(Txn::Dispute {
+                client_id: 1,
+                txn_id: 2
+            })?,
+            ProcessEvent::Rejected(RejectReason::TxnExpired)
+        );
+
+        Ok(())
+    }
 }
@@ -1,6 +1,72 @@
-mod account;
-pub mod application;
+//! A toy transaction engine.
+//!
+//! Deposits, withdrawals, disputes, resolves and chargebacks are
+//! applied to client accounts held in a [`Ledger`]. Amounts are u128
+//! values in units of 0.0001, so `1.5` is `15000`.
+//!
+//! ```
+//! use toy_txn_engine::{Ledger, ProcessEvent, RejectReason, Txn};
+//!
+//! let mut ledger = Ledger::new();
+//! ledger.process_txn(Txn::Deposit { client_id: 1, txn_id: 1, amount: 10_0000 })?;
+//! ledger.process_txn(Txn::Dispute { client_id: 1, txn_id: 1 })?;
+//!
+//! let account = ledger.account(1).unwrap();
+//! assert_eq!(account.available, 0);
+//! assert_eq!(account.held, 10_0000);
+//!
+//! let outcome = ledger.process_txn(Txn::Withdraw { client_id: 1, txn_id: 2, amount: 1 })?;
+//! assert_eq!(outcome, ProcessEvent::Rejected(RejectReason::InsufficientFunds));
+//! # Ok::<(), ProcessEvent>(())
+//! ```
+//!
+//! Records read from a csv can be applied with
+//! [`Ledger::process_transaction`], or spread over threads with
+//! [`sharded::process_sharded`] and [`pipeline::process_pipelined`].
+pub mod account;
+pub mod audit;
+pub mod books;
+pub mod config;
+pub mod decision_log;
+pub mod diff;
 pub mod events;
-mod ledger;
-mod record;
-mod transaction;
+pub mod http;
+#[cfg(feature = "async")]
+pub mod ingest;
+pub mod ledger;
+pub mod merkle;
+pub mod metrics;
+pub mod observer;
+pub mod pipeline;
+pub mod policy;
+pub mod reconcile;
+pub mod record;
+pub mod server;
+pub mod sharded;
+pub mod shared;
+pub mod snapshot;
+pub mod statement;
+pub mod summary;
+pub mod transaction;
+
+pub use account::{Account, AccountTable};
+pub use audit::{AuditReport, Auditor};
+pub use books::{BookAccount, Books, Posting, TrialBalance};
+pub use config::{LedgerBuilder, LedgerConfig};
+pub use decision_log::{DecisionLog, Level, LogFormat};
+pub use diff::AccountsDiff;
+pub use events::{ProcessEvent, RejectReason};
+pub use http::HttpServer;
+pub use ledger::{DisputeWindow, Ledger};
+pub use merkle::{InclusionProof, MerkleTree};
+pub use metrics::Metrics;
+pub use observer::{DomainEvent, EventRecord, JsonlEventSink, LedgerObserver};
+pub use policy::{PolicyContext, PolicyDecision, PolicyLimits, TxnPolicy};
+pub use reconcile::{ReconcileReport, Tolerances};
+pub use record::Record;
+pub use server::Server;
+pub use shared::{LedgerView, SharedLedger};
+pub use snapshot::{Snapshot, SnapshotStore};
+pub use statement::{Statement, StatementLine};
+pub use summary::{LedgerStats, RunSummary};
+pub use transaction::{DisputableTxn, Txn, TxnKind, TxnState};
@@ -1,10 +1,15 @@
 use std::process;
 
-use toy_txn_engine::{application::the_app, events::ProcessEvent};
+use application::the_app;
+use toy_txn_engine::ProcessEvent;
+
+mod application;
 
 fn main() {
     match the_app() {
-        Ok(ProcessEvent::ProcessComplete) => {}
+        Ok(ProcessEvent::ProcessComplete)
+        | Ok(ProcessEvent::Rejected(_))
+        | Ok(ProcessEvent::Flagged(_)) => {}
         Ok(ProcessEvent::ExternalErr(err)) => {
             println!("App failed during process: {err}");
             process::exit(1);
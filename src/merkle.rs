@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{ledger::Ledger, snapshot::AccountSnapshot};
+
+/// A sha256 digest.
+pub type Hash = [u8; 32];
+
+// leaves and nodes are hashed with different prefixes,
+// so a node can not be passed off as a leaf.
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+
+/// Format a hash as lowercase hex.
+pub fn to_hex(hash: &Hash) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Parse a hash from hex, as formatted by [`to_hex`].
+pub fn from_hex(hex: &str) -> Option<Hash> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0; 32];
+    for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(hash)
+}
+
+/// (de)serialise a hash as a hex string.
+mod hex {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::Hash;
+
+    pub fn serialize<S: Serializer>(hash: &Hash, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(hash))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Hash, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        super::from_hex(&hex).ok_or_else(|| Error::custom("invalid hash"))
+    }
+}
+
+/// The hash of an account as a leaf of the tree.
+///
+/// The account is encoded as its client id, available and held
+/// balances, frozen flag and open disputes in id order, each
+/// integer big endian and the disputes preceded by their count.
+pub fn leaf_hash(account: &AccountSnapshot) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF]);
+    hasher.update(account.client.to_be_bytes());
+    hasher.update(account.available.to_be_bytes());
+    hasher.update(account.held.to_be_bytes());
+    hasher.update([account.frozen as u8]);
+    hasher.update((account.disputes.len() as u32).to_be_bytes());
+    for tx in &account.disputes {
+        hasher.update(tx.to_be_bytes());
+    }
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// A merkle tree over the accounts of a ledger, in client id order.
+///
+/// Pairs of nodes are hashed together level by level, and a node
+/// left without a pair moves up a level unchanged. The root is a
+/// canonical hash of the accounts, equal for equal accounts however
+/// they were reached. The root of a ledger without accounts is the
+/// sha256 of nothing.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    accounts: Vec<AccountSnapshot>,
+    // leaves first, the root last.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(ledger: &Ledger) -> Self {
+        Self::from_accounts(ledger.snapshot_accounts())
+    }
+
+    /// The tree over `accounts`, which must be in client id order.
+    pub fn from_accounts(accounts: Vec<AccountSnapshot>) -> Self {
+        let mut levels = vec![accounts.iter().map(leaf_hash).collect::<Vec<_>>()];
+        while levels[levels.len() - 1].len() > 1 {
+            let level = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!("chunks of two"),
+                })
+                .collect();
+            levels.push(level);
+        }
+        Self { accounts, levels }
+    }
+
+    pub fn root(&self) -> Hash {
+        match self.levels[self.levels.len() - 1].first() {
+            Some(root) => *root,
+            None => Sha256::digest([]).into(),
+        }
+    }
+
+    /// A proof that the account of `client` is in the tree,
+    /// if the client has an account.
+    pub fn prove(&self, client: u16) -> Option<InclusionProof> {
+        let mut index = self
+            .accounts
+            .binary_search_by_key(&client, |account| account.client)
+            .ok()?;
+        let account = self.accounts[index].clone();
+
+        let mut path = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if let Some(hash) = level.get(sibling) {
+                path.push(ProofStep {
+                    hash: *hash,
+                    left: sibling < index,
+                });
+            }
+            index /= 2;
+        }
+        Some(InclusionProof { account, path })
+    }
+}
+
+/// A sibling on the path from a leaf to the root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProofStep {
+    #[serde(with = "hex")]
+    pub hash: Hash,
+    /// whether the sibling is hashed on the left.
+    pub left: bool,
+}
+
+/// Proof that an account, with its balances, is part of
+/// the state with a given root.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub account: AccountSnapshot,
+    /// from the leaf up.
+    pub path: Vec<ProofStep>,
+}
+
+impl InclusionProof {
+    /// Whether hashing the account up the path gives `root`.
+    pub fn verify(&self, root: &Hash) -> bool {
+        let hash = self
+            .path
+            .iter()
+            .fold(leaf_hash(&self.account), |hash, step| match step.left {
+                true => node_hash(&step.hash, &hash),
+                false => node_hash(&hash, &step.hash),
+            });
+        hash == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{events::ProcessEvent, ledger::Ledger, transaction::Txn};
+
+    use super::{from_hex, to_hex, InclusionProof, MerkleTree};
+
+    #[test]
+    fn test_merkle_proofs() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::new();
+        let empty = ledger.state_hash();
+
+        // an odd number of accounts leaves nodes without pairs
+        for txn_id in 1..=7u32 {
+            ledger.process_txn(Txn::Deposit {
+                client_id: txn_id as u16 * 3,
+                txn_id,
+                amount: txn_id as u128 * 1_0000,
+            })?;
+        }
+        ledger.process_txn(Txn::Dispute {
+            client_id: 6,
+            txn_id: 2,
+        })?;
+
+        let tree = MerkleTree::new(&ledger);
+        let root = tree.root();
+        assert_ne!(root, empty);
+        assert_eq!(root, ledger.state_hash());
+        assert_eq!(from_hex(&to_hex(&root)), Some(root));
+
+        for client in (3..=21).step_by(3) {
+            let proof = tree.prove(client).unwrap();
+            assert!(proof.verify(&root));
+
+            let json = serde_json::to_string(&proof).unwrap();
+            assert_eq!(
+                serde_json::from_str::<InclusionProof>(&json).unwrap(),
+                proof
+            );
+        }
+        assert!(tree.prove(4).is_none());
+
+        // a proof does not verify a different balance
+        let mut proof = tree.prove(6).unwrap();
+        assert_eq!(proof.account.disputes, vec![2]);
+        proof.account.held += 1;
+        assert!(!proof.verify(&root));
+
+        // and the root changes with the state
+        ledger.process_txn(Txn::Resolve {
+            client_id: 6,
+            txn_id: 2,
+        })?;
+        assert_ne!(ledger.state_hash(), root);
+
+        Ok(())
+    }
+}
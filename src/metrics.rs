@@ -0,0 +1,344 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::{events::ProcessEvent, transaction::Txn};
+
+/// upper bounds of the processing latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 9] = [
+    0.000_001, 0.000_005, 0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01,
+];
+
+/// upper bounds of the amount buckets, in whole units.
+const AMOUNT_BUCKETS: [f64; 7] = [1.0, 10.0, 100.0, 1_000.0, 10_000.0, 100_000.0, 1_000_000.0];
+
+/// how long a metrics request may stall reading or writing.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counts of observations at or below each bound, with their sum.
+#[derive(Debug, Clone, PartialEq)]
+struct Histogram {
+    bounds: &'static [f64],
+    // not cumulative, one per bound and one for +Inf.
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket] += 1;
+        self.sum += value;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{name}_bucket{{{labels}le=\"{bound}\"}} {cumulative}");
+        }
+        cumulative += self.counts[self.bounds.len()];
+        let _ = writeln!(out, "{name}_bucket{{{labels}le=\"+Inf\"}} {cumulative}");
+        let labels = labels.trim_end_matches(',');
+        let labels = match labels {
+            "" => String::new(),
+            labels => format!("{{{labels}}}"),
+        };
+        let _ = writeln!(out, "{name}_sum{labels} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{labels} {cumulative}");
+    }
+}
+
+#[derive(Debug)]
+struct Registry {
+    // by transaction type and outcome.
+    transactions: BTreeMap<(&'static str, &'static str), u64>,
+    latency: Histogram,
+    // by transaction type, deposits and withdrawals only.
+    amounts: BTreeMap<&'static str, Histogram>,
+    accounts: i64,
+    history: i64,
+    open_disputes: i64,
+}
+
+/// Metrics of the ledgers processing transactions, in the
+/// Prometheus text format.
+///
+/// A handle is cheap to clone and every clone updates the same
+/// metrics, so the ledgers of a sharded run can share one. The
+/// gauges are kept as changes rather than set, so they add up
+/// across shards.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How the state of a ledger changed while it processed a transaction.
+pub(crate) struct Change {
+    pub accounts: i64,
+    pub history: i64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry {
+            transactions: BTreeMap::new(),
+            latency: Histogram::new(&LATENCY_BUCKETS),
+            amounts: BTreeMap::new(),
+            accounts: 0,
+            history: 0,
+            open_disputes: 0,
+        };
+        Self {
+            registry: Arc::new(Mutex::new(registry)),
+        }
+    }
+
+    /// Record a processed transaction.
+    pub(crate) fn observe(
+        &self,
+        txn: &Txn,
+        outcome: &Result<ProcessEvent, ProcessEvent>,
+        latency: Duration,
+        change: Change,
+    ) {
+        // metrics are best effort, a poisoned lock skips them.
+        let Ok(mut registry) = self.registry.lock() else {
+            return;
+        };
+        let r#type = txn.type_name();
+        let label = match outcome {
+            Ok(ProcessEvent::ProcessComplete) => "applied",
+            Ok(ProcessEvent::Flagged(_)) => "flagged",
+            Ok(ProcessEvent::Rejected(_)) => "rejected",
+            Ok(ProcessEvent::ExternalErr(_)) | Err(_) => "error",
+        };
+        *registry.transactions.entry((r#type, label)).or_default() += 1;
+        registry.latency.observe(latency.as_secs_f64());
+        if matches!(txn, Txn::Deposit { .. } | Txn::Withdraw { .. }) {
+            registry
+                .amounts
+                .entry(r#type)
+                .or_insert_with(|| Histogram::new(&AMOUNT_BUCKETS))
+                .observe(txn.amount() as f64 / 10_000.0);
+        }
+        registry.accounts += change.accounts;
+        registry.history += change.history;
+        if label == "applied" || label == "flagged" {
+            match txn {
+                Txn::Dispute { .. } => registry.open_disputes += 1,
+                Txn::Resolve { .. } | Txn::ChargeBack { .. } => registry.open_disputes -= 1,
+                Txn::Deposit { .. } | Txn::Withdraw { .. } => {}
+            }
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let Ok(registry) = self.registry.lock() else {
+            return out;
+        };
+
+        out.push_str(
+            "# HELP txn_engine_transactions_total Transactions processed, by type and outcome.\n",
+        );
+        out.push_str("# TYPE txn_engine_transactions_total counter\n");
+        for ((r#type, outcome), count) in &registry.transactions {
+            let _ = writeln!(
+                out,
+                "txn_engine_transactions_total{{type=\"{type}\",outcome=\"{outcome}\"}} {count}"
+            );
+        }
+
+        out.push_str("# HELP txn_engine_processing_seconds Time taken to process a transaction.\n");
+        out.push_str("# TYPE txn_engine_processing_seconds histogram\n");
+        registry
+            .latency
+            .render(&mut out, "txn_engine_processing_seconds", "");
+
+        out.push_str("# HELP txn_engine_amount Amounts of deposits and withdrawals, by type.\n");
+        out.push_str("# TYPE txn_engine_amount histogram\n");
+        for (r#type, histogram) in &registry.amounts {
+            histogram.render(&mut out, "txn_engine_amount", &format!("type=\"{type}\","));
+        }
+
+        let gauges = [
+            ("accounts", "Client accounts opened.", registry.accounts),
+            (
+                "history_size",
+                "Transactions kept in the history.",
+                registry.history,
+            ),
+            (
+                "open_disputes",
+                "Disputes not yet resolved or charged back.",
+                registry.open_disputes,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP txn_engine_{name} {help}");
+            let _ = writeln!(out, "# TYPE txn_engine_{name} gauge");
+            let _ = writeln!(out, "txn_engine_{name} {value}");
+        }
+        out
+    }
+
+    /// Write the metrics to `path`, replacing the file in one step
+    /// so a collector never reads a partial dump.
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), ProcessEvent> {
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        let io_err =
+            |err: std::io::Error| ProcessEvent::ExternalErr(format!("{}: {err}", path.display()));
+        fs::write(&partial, self.render()).map_err(io_err)?;
+        fs::rename(&partial, path).map_err(io_err)
+    }
+
+    /// Serve the metrics over http at `addr`, on a background thread,
+    /// for as long as the process runs. Returns the address served,
+    /// which has the port chosen when `addr` asks for port 0.
+    ///
+    /// Every request is answered with the metrics, whatever its path,
+    /// each on its own thread.
+    pub fn serve(&self, addr: impl ToSocketAddrs) -> Result<SocketAddr, ProcessEvent> {
+        let io_err = |err: std::io::Error| ProcessEvent::ExternalErr(err.to_string());
+        let listener = TcpListener::bind(addr).map_err(io_err)?;
+        let addr = listener.local_addr().map_err(io_err)?;
+        let metrics = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let metrics = metrics.clone();
+                thread::spawn(move || metrics.answer(stream));
+            }
+        });
+        Ok(addr)
+    }
+
+    /// Answer a request with the metrics. A client which stalls is
+    /// dropped, there is no one to report a failure to.
+    fn answer(&self, mut stream: TcpStream) {
+        let timeout = Some(CONNECTION_TIMEOUT);
+        if stream.set_read_timeout(timeout).is_err() || stream.set_write_timeout(timeout).is_err() {
+            return;
+        }
+        // read the request line and headers, the request is not used.
+        let mut reader = BufReader::new(&mut stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+            line.clear();
+        }
+        let body = self.render();
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use crate::{events::ProcessEvent, ledger::Ledger, transaction::Txn};
+
+    use super::Metrics;
+
+    #[test]
+    fn test_metrics() -> Result<(), ProcessEvent> {
+        let metrics = Metrics::new();
+        let mut ledger = Ledger::builder().metrics(metrics.clone()).build();
+        let txns = [
+            Txn::Deposit {
+                client_id: 1,
+                txn_id: 1,
+                amount: 10_0000,
+            },
+            Txn::Deposit {
+                client_id: 2,
+                txn_id: 2,
+                amount: 500_0000,
+            },
+            Txn::Withdraw {
+                client_id: 2,
+                txn_id: 3,
+                amount: 900_0000,
+            },
+            Txn::Dispute {
+                client_id: 1,
+                txn_id: 1,
+            },
+            Txn::Dispute {
+                client_id: 2,
+                txn_id: 2,
+            },
+            Txn::Resolve {
+                client_id: 2,
+                txn_id: 2,
+            },
+        ];
+        for txn in txns {
+            ledger.process_txn(txn)?;
+        }
+
+        let text = metrics.render();
+        let lines: Vec<&str> = text.lines().collect();
+        for expected in [
+            r#"txn_engine_transactions_total{type="deposit",outcome="applied"} 2"#,
+            r#"txn_engine_transactions_total{type="withdrawal",outcome="rejected"} 1"#,
+            r#"txn_engine_transactions_total{type="dispute",outcome="applied"} 2"#,
+            "txn_engine_processing_seconds_count 6",
+            r#"txn_engine_amount_bucket{type="deposit",le="10"} 1"#,
+            r#"txn_engine_amount_bucket{type="deposit",le="100"} 1"#,
+            r#"txn_engine_amount_bucket{type="deposit",le="1000"} 2"#,
+            r#"txn_engine_amount_sum{type="withdrawal"} 900"#,
+            "txn_engine_accounts 2",
+            "txn_engine_history_size 2",
+            "txn_engine_open_disputes 1",
+        ] {
+            assert!(lines.contains(&expected), "{expected} missing from\n{text}");
+        }
+
+        // and over http
+        let addr = metrics.serve("127.0.0.1:0")?;
+
+        // a client which sends nothing does not hold up the others
+        let _idle = TcpStream::connect(addr).unwrap();
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with(&text));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{ProcessEvent, RejectReason},
+    record::decimal,
+    transaction::TxnKind,
+};
+
+/// A change to the ledger, emitted once the transaction
+/// which caused it has been processed.
+///
+/// Amounts are in units of 0.0001, and serialise as decimal strings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DomainEvent {
+    /// the client's first deposit or withdrawal.
+    AccountOpened {
+        client: u16,
+    },
+    Deposited {
+        client: u16,
+        tx: u32,
+        #[serde(with = "decimal")]
+        amount: u128,
+    },
+    Withdrew {
+        client: u16,
+        tx: u32,
+        #[serde(with = "decimal")]
+        amount: u128,
+    },
+    /// `client` is the owner of the disputed transaction,
+    /// which may not be the client who disputed it.
+    DisputeOpened {
+        client: u16,
+        tx: u32,
+        kind: TxnKind,
+        #[serde(with = "decimal")]
+        amount: u128,
+    },
+    Resolved {
+        client: u16,
+        tx: u32,
+        kind: TxnKind,
+        #[serde(with = "decimal")]
+        amount: u128,
+    },
+    ChargedBack {
+        client: u16,
+        tx: u32,
+        kind: TxnKind,
+        #[serde(with = "decimal")]
+        amount: u128,
+    },
+    AccountFrozen {
+        client: u16,
+    },
+    /// the transaction left the dispute window.
+    Expired {
+        client: u16,
+        tx: u32,
+    },
+    /// `client` is the client on the rejected record.
+    Rejected {
+        client: u16,
+        tx: u32,
+        r#type: String,
+        reason: RejectReason,
+    },
+}
+
+impl DomainEvent {
+    /// the client whose account the event concerns.
+    pub fn client(&self) -> u16 {
+        match self {
+            Self::AccountOpened { client }
+            | Self::Deposited { client, .. }
+            | Self::Withdrew { client, .. }
+            | Self::DisputeOpened { client, .. }
+            | Self::Resolved { client, .. }
+            | Self::ChargedBack { client, .. }
+            | Self::AccountFrozen { client }
+            | Self::Expired { client, .. }
+            | Self::Rejected { client, .. } => *client,
+        }
+    }
+}
+
+/// A domain event and the position in the input of the
+/// record which caused it, as written by `JsonlEventSink`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub event: DomainEvent,
+}
+
+/// Receives domain events from a ledger.
+///
+/// Observers are called synchronously, in the order they were
+/// subscribed, after each transaction has been processed. An error
+/// from an observer stops processing like a malformed record does.
+pub trait LedgerObserver: Send {
+    fn on_event(&mut self, seq: u64, event: &DomainEvent) -> Result<(), ProcessEvent>;
+}
+
+// lets the subscriber keep a handle on an observer to inspect it later.
+impl<T: LedgerObserver> LedgerObserver for Arc<Mutex<T>> {
+    fn on_event(&mut self, seq: u64, event: &DomainEvent) -> Result<(), ProcessEvent> {
+        self.lock()
+            .map_err(|_| ProcessEvent::ExternalErr("observer poisoned".to_owned()))?
+            .on_event(seq, event)
+    }
+}
+
+/// Writes every event as a line of json.
+pub struct JsonlEventSink<W: Write + Send> {
+    out: W,
+    written: u64,
+}
+
+impl<W: Write + Send> JsonlEventSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, written: 0 }
+    }
+
+    /// Number of bytes written, which is the offset in the
+    /// journal of the next event.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write + Send> LedgerObserver for JsonlEventSink<W> {
+    fn on_event(&mut self, seq: u64, event: &DomainEvent) -> Result<(), ProcessEvent> {
+        let record = EventRecord {
+            seq,
+            event: event.clone(),
+        };
+        let mut line = serde_json::to_vec(&record)
+            .map_err(|err| ProcessEvent::ExternalErr(format!("failed to write event: {err}")))?;
+        line.push(b'\n');
+        self.out
+            .write_all(&line)
+            .map_err(|err| ProcessEvent::ExternalErr(format!("failed to write event: {err}")))?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::RejectReason;
+    use crate::transaction::TxnKind;
+
+    use super::{DomainEvent, EventRecord, JsonlEventSink, LedgerObserver};
+
+    #[test]
+    fn test_jsonl_event_sink() {
+        let mut sink = JsonlEventSink::new(Vec::new());
+        let events = [
+            DomainEvent::Deposited {
+                client: 1,
+                tx: 1,
+                amount: 1_5000,
+            },
+            DomainEvent::DisputeOpened {
+                client: 1,
+                tx: 1,
+                kind: TxnKind::Deposit,
+                amount: 1_5000,
+            },
+            DomainEvent::Rejected {
+                client: 2,
+                tx: 7,
+                r#type: "withdrawal".to_owned(),
+                reason: RejectReason::InsufficientFunds,
+            },
+        ];
+        for (seq, event) in events.iter().enumerate() {
+            sink.on_event(seq as u64 + 1, event).unwrap();
+        }
+
+        let out = String::from_utf8(std::mem::take(&mut sink.out)).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            r#"{"seq":1,"event":"deposited","client":1,"tx":1,"amount":"1.5000"}"#
+        );
+        assert_eq!(
+            lines[2],
+            r#"{"seq":3,"event":"rejected","client":2,"tx":7,"type":"withdrawal","reason":"insufficient_funds"}"#
+        );
+
+        // every line reads back as the event which was written
+        for (line, event) in lines.iter().zip(events) {
+            let record: EventRecord = serde_json::from_str(line).unwrap();
+            assert_eq!(record.event, event);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+use crate::{ledger::Ledger, record::Record, transaction::Txn};
+
+// raw records are handed to parsers in batches to keep
+// channel overhead off the per record cost.
+const BATCH_SIZE: usize = 1024;
+const BATCHES_IN_FLIGHT: usize = 4;
+
+type ParseError = Box<dyn Error + Send + Sync>;
+
+// raw records read from the csv, and the read error
+// which ended the input if there was one.
+struct RawBatch {
+    records: Vec<csv::ByteRecord>,
+    error: Option<csv::Error>,
+}
+
+/// Process a csv of transactions, parsing records on `parsers`
+/// worker threads.
+///
+/// A reader thread reads raw byte records and deals them out in
+/// batches, round robin, to the parsers. The calling thread applies
+/// the parsed transactions to `ledger`, collecting batches from the
+/// parsers in the same round robin order, so transactions are applied
+/// in exactly the order of the input.
+///
+/// Processing stops at the first record which fails to read or parse,
+/// after every record before it has been applied, as it would
+/// when reading the file on a single thread.
+pub fn process_pipelined<R: Read + Send>(
+    reader: &mut csv::Reader<R>,
+    parsers: usize,
+    ledger: &mut Ledger,
+) -> Result<(), Box<dyn Error>> {
+    let parsers = parsers.max(1);
+    let headers = reader.byte_headers()?.clone();
+    let headers = &headers;
+
+    thread::scope(|scope| {
+        let mut raw_senders = Vec::with_capacity(parsers);
+        let mut parsed_receivers = Vec::with_capacity(parsers);
+        for _ in 0..parsers {
+            let (raw_sender, raw_receiver) = mpsc::sync_channel::<RawBatch>(BATCHES_IN_FLIGHT);
+            let (parsed_sender, parsed_receiver) = mpsc::sync_channel(BATCHES_IN_FLIGHT);
+            raw_senders.push(raw_sender);
+            parsed_receivers.push(parsed_receiver);
+            scope.spawn(move || parse(headers, raw_receiver, parsed_sender));
+        }
+        scope.spawn(move || read(reader, raw_senders));
+
+        apply(parsed_receivers, ledger)
+    })
+}
+
+/// deal batches of raw records out to the parsers in turn.
+fn read<R: Read>(reader: &mut csv::Reader<R>, senders: Vec<SyncSender<RawBatch>>) {
+    for sender in senders.iter().cycle() {
+        let mut batch = RawBatch {
+            records: Vec::with_capacity(BATCH_SIZE),
+            error: None,
+        };
+        let mut record = csv::ByteRecord::new();
+        while batch.records.len() < BATCH_SIZE {
+            match reader.read_byte_record(&mut record) {
+                Ok(true) => batch.records.push(record.clone()),
+                Ok(false) => break,
+                Err(err) => {
+                    batch.error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        let done = batch.records.len() < BATCH_SIZE || batch.error.is_some();
+        // the applier only hangs up once it has stopped.
+        if sender.send(batch).is_err() || done {
+            return;
+        }
+    }
+}
+
+fn parse(
+    headers: &csv::ByteRecord,
+    receiver: Receiver<RawBatch>,
+    sender: SyncSender<Vec<Result<Txn, ParseError>>>,
+) {
+    for batch in receiver {
+        let mut parsed: Vec<Result<Txn, ParseError>> = batch
+            .records
+            .iter()
+            .map(|raw| {
+                let record: Record = raw.deserialize(Some(headers))?;
+                Ok(Txn::from_record(record)?)
+            })
+            .collect();
+        if let Some(err) = batch.error {
+            parsed.push(Err(err.into()));
+        }
+        if sender.send(parsed).is_err() {
+            return;
+        }
+    }
+}
+
+/// apply parsed batches in input order until the input is exhausted.
+fn apply(
+    receivers: Vec<Receiver<Vec<Result<Txn, ParseError>>>>,
+    ledger: &mut Ledger,
+) -> Result<(), Box<dyn Error>> {
+    for receiver in receivers.iter().cycle() {
+        // the reader stops after a short batch, so the
+        // next parser in turn hanging up means the end.
+        let Ok(batch) = receiver.recv() else {
+            return Ok(());
+        };
+        for txn in batch {
+            ledger.process_txn(txn.map_err(|err| err as Box<dyn Error>)?)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::ledger::Ledger;
+    use crate::record::Record;
+
+    use super::process_pipelined;
+
+    fn transactions(rows: u32) -> String {
+        let mut csv = String::from("type, client, tx, amount\n");
+        for tx in 1..=rows {
+            let client = tx % 10;
+            let line = match tx % 5 {
+                0 => format!("withdrawal, {client}, {tx}, 1.5\n"),
+                3 => format!("dispute, {client}, {}, \n", tx - 1),
+                4 if tx % 3 == 0 => format!("chargeback, {client}, {}, \n", tx - 2),
+                _ => format!("deposit, {client}, {tx}, {}.{}\n", tx % 13, tx % 7),
+            };
+            csv.push_str(&line);
+        }
+        csv
+    }
+
+    fn reader(csv: &str) -> csv::Reader<&[u8]> {
+        csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(csv.as_bytes())
+    }
+
+    fn sequential(csv: &str) -> Result<Ledger, Box<dyn Error>> {
+        let mut ledger = Ledger::new();
+        for result in reader(csv).deserialize() {
+            let record: Record = result?;
+            ledger.process_transaction(record)?;
+        }
+        Ok(ledger)
+    }
+
+    fn output(ledger: &Ledger) -> String {
+        let mut out = Vec::new();
+        ledger.write_accounts(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_pipelined_matches_sequential() -> Result<(), Box<dyn Error>> {
+        // cover inputs which end on and off a batch boundary
+        for rows in [0, 10, 1024 * 3, 5000] {
+            let csv = transactions(rows);
+            let expected = sequential(&csv)?;
+            for parsers in 1..=4 {
+                let mut ledger = Ledger::new();
+                process_pipelined(&mut reader(&csv), parsers, &mut ledger)?;
+                assert_eq!(output(&ledger), output(&expected));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipelined_stops_at_malformed_record() -> Result<(), Box<dyn Error>> {
+        let valid = transactions(3000);
+        let mut csv = valid.clone();
+        csv.push_str("bogus, 1, 99999, 1.0\n");
+        csv.push_str("deposit, 1, 100000, 1000.0\n");
+
+        for parsers in 1..=3 {
+            let mut ledger = Ledger::new();
+            let result = process_pipelined(&mut reader(&csv), parsers, &mut ledger);
+            assert!(result.is_err());
+            // everything before the malformed record was applied, nothing after
+            assert_eq!(output(&ledger), output(&sequential(&valid)?));
+        }
+        Ok(())
+    }
+}
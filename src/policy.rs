@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{account::Account, record::optional_decimal, transaction::Txn};
+
+/// What a policy decided about a transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyDecision {
+    /// let the ledger apply the transaction.
+    Approve,
+    /// do not apply the transaction, for this reason.
+    Reject(String),
+    /// apply the transaction, but report it for this reason.
+    Flag(String),
+}
+
+/// What a policy can see of the ledger when deciding.
+pub struct PolicyContext<'a> {
+    /// position of the transaction in the input, starting from 1.
+    pub seq: u64,
+    /// the account of the client on the transaction, if it is open.
+    pub account: Option<&'a Account>,
+}
+
+/// A business rule checked before a transaction is applied.
+///
+/// Policies are evaluated in the order they were added to the ledger.
+/// The first rejection stops the chain and the transaction is not
+/// applied. Flags from every policy are collected and reported
+/// if the transaction is applied.
+pub trait TxnPolicy: Send {
+    fn evaluate(&mut self, txn: &Txn, context: &PolicyContext) -> PolicyDecision;
+
+    /// Called on every policy once a transaction has been applied,
+    /// with the account as it is after the transaction. Transactions
+    /// rejected by a policy or by the ledger are not passed on.
+    fn applied(&mut self, _txn: &Txn, _context: &PolicyContext) {}
+}
+
+/// Rejects withdrawals larger than `limit`.
+pub struct MaxWithdrawal {
+    pub limit: u128,
+}
+
+impl TxnPolicy for MaxWithdrawal {
+    fn evaluate(&mut self, txn: &Txn, _context: &PolicyContext) -> PolicyDecision {
+        if matches!(txn, Txn::Withdraw { .. }) && txn.amount() > self.limit {
+            let limit = Txn::u128_to_decimal_str(self.limit).unwrap_or_default();
+            return PolicyDecision::Reject(format!("withdrawal exceeds limit of {limit}"));
+        }
+        PolicyDecision::Approve
+    }
+}
+
+/// Rejects every transaction made by the listed clients.
+pub struct BlockedClients {
+    pub clients: HashSet<u16>,
+}
+
+impl TxnPolicy for BlockedClients {
+    fn evaluate(&mut self, txn: &Txn, _context: &PolicyContext) -> PolicyDecision {
+        if self.clients.contains(&txn.client_id()) {
+            return PolicyDecision::Reject(format!("client {} is blocked", txn.client_id()));
+        }
+        PolicyDecision::Approve
+    }
+}
+
+/// Flags a client's deposit when it has already made `max_deposits`
+/// deposits within the last `window_txns` transactions of the input.
+///
+/// Only applied deposits count, not those rejected by a later policy
+/// or by the ledger.
+pub struct DepositVelocity {
+    pub max_deposits: usize,
+    pub window_txns: u64,
+    // sequence numbers of each client's recent deposits, oldest first.
+    recent: HashMap<u16, VecDeque<u64>>,
+}
+
+impl DepositVelocity {
+    pub fn new(max_deposits: usize, window_txns: u64) -> Self {
+        Self {
+            max_deposits,
+            window_txns,
+            recent: HashMap::new(),
+        }
+    }
+}
+
+impl TxnPolicy for DepositVelocity {
+    fn evaluate(&mut self, txn: &Txn, context: &PolicyContext) -> PolicyDecision {
+        if !matches!(txn, Txn::Deposit { .. }) {
+            return PolicyDecision::Approve;
+        }
+
+        let Some(recent) = self.recent.get_mut(&txn.client_id()) else {
+            return PolicyDecision::Approve;
+        };
+        while recent
+            .front()
+            .is_some_and(|seq| context.seq - seq >= self.window_txns)
+        {
+            recent.pop_front();
+        }
+        if recent.len() >= self.max_deposits {
+            return PolicyDecision::Flag(format!(
+                "more than {} deposits within {} transactions",
+                self.max_deposits, self.window_txns
+            ));
+        }
+        PolicyDecision::Approve
+    }
+
+    fn applied(&mut self, txn: &Txn, context: &PolicyContext) {
+        if !matches!(txn, Txn::Deposit { .. }) {
+            return;
+        }
+        // only the last `max_deposits` are needed to decide.
+        let recent = self.recent.entry(txn.client_id()).or_default();
+        recent.push_back(context.seq);
+        if recent.len() > self.max_deposits {
+            recent.pop_front();
+        }
+    }
+}
+
+/// Limits of the built-in policies, as set in a `LedgerConfig`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyLimits {
+    /// largest single withdrawal, as a decimal string.
+    #[serde(with = "optional_decimal", skip_serializing_if = "Option::is_none")]
+    pub max_withdrawal: Option<u128>,
+    /// clients whose transactions are all rejected.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blocked_clients: Vec<u16>,
+    /// flag deposits made faster than this.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deposit_velocity: Option<VelocityLimit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VelocityLimit {
+    pub max_deposits: usize,
+    pub window_txns: u64,
+}
+
+impl PolicyLimits {
+    /// The built-in policies for the limits which are set.
+    pub fn policies(&self) -> Vec<Box<dyn TxnPolicy>> {
+        let mut policies: Vec<Box<dyn TxnPolicy>> = Vec::new();
+        if !self.blocked_clients.is_empty() {
+            policies.push(Box::new(BlockedClients {
+                clients: self.blocked_clients.iter().copied().collect(),
+            }));
+        }
+        if let Some(limit) = self.max_withdrawal {
+            policies.push(Box::new(MaxWithdrawal { limit }));
+        }
+        if let Some(velocity) = &self.deposit_velocity {
+            policies.push(Box::new(DepositVelocity::new(
+                velocity.max_deposits,
+                velocity.window_txns,
+            )));
+        }
+        policies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        events::{ProcessEvent, RejectReason},
+        ledger::Ledger,
+        transaction::Txn,
+    };
+
+    use super::{DepositVelocity, PolicyContext, PolicyDecision, PolicyLimits, TxnPolicy};
+
+    fn deposit(client_id: u16) -> Txn {
+        Txn::Deposit {
+            client_id,
+            txn_id: 1,
+            amount: 1,
+        }
+    }
+
+    #[test]
+    fn test_deposit_velocity() {
+        let mut policy = DepositVelocity::new(2, 5);
+        let decide = |policy: &mut DepositVelocity, seq, client| {
+            let context = PolicyContext { seq, account: None };
+            let decision = policy.evaluate(&deposit(client), &context);
+            policy.applied(&deposit(client), &context);
+            decision
+        };
+
+        assert_eq!(decide(&mut policy, 1, 1), PolicyDecision::Approve);
+        assert_eq!(decide(&mut policy, 2, 1), PolicyDecision::Approve);
+        // other clients are counted separately
+        assert_eq!(decide(&mut policy, 3, 2), PolicyDecision::Approve);
+        assert!(matches!(decide(&mut policy, 4, 1), PolicyDecision::Flag(_)));
+        // the deposits at 1 and 2 have left the window
+        assert_eq!(decide(&mut policy, 9, 1), PolicyDecision::Approve);
+    }
+
+    /// rejects deposits larger than `limit`.
+    struct MaxDeposit {
+        limit: u128,
+    }
+
+    impl TxnPolicy for MaxDeposit {
+        fn evaluate(&mut self, txn: &Txn, _context: &PolicyContext) -> PolicyDecision {
+            if matches!(txn, Txn::Deposit { .. }) && txn.amount() > self.limit {
+                return PolicyDecision::Reject("deposit too large".to_owned());
+            }
+            PolicyDecision::Approve
+        }
+    }
+
+    #[test]
+    fn test_deposit_velocity_counts_applied() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::builder()
+            .policy(DepositVelocity::new(1, 10))
+            .policy(MaxDeposit { limit: 5_0000 })
+            .build();
+        let deposit = |client_id, txn_id, amount| Txn::Deposit {
+            client_id,
+            txn_id,
+            amount,
+        };
+
+        // rejected by a later policy, so not counted
+        let event = ledger.process_txn(deposit(1, 1, 9_0000))?;
+        assert!(matches!(event, ProcessEvent::Rejected(_)));
+        let event = ledger.process_txn(deposit(1, 2, 1_0000))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+
+        // rejected by the ledger, so not counted
+        ledger.process_txn(deposit(2, 3, 1_0000))?;
+        let event = ledger.process_txn(deposit(3, 3, 1_0000))?;
+        assert_eq!(event, ProcessEvent::Rejected(RejectReason::DuplicateTxn));
+        let event = ledger.process_txn(deposit(3, 4, 1_0000))?;
+        assert_eq!(event, ProcessEvent::ProcessComplete);
+
+        // applied deposits are
+        let event = ledger.process_txn(deposit(3, 5, 1_0000))?;
+        assert!(matches!(event, ProcessEvent::Flagged(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_limits_from_json() {
+        let raw = r#"{
+            "max_withdrawal": "100.5",
+            "blocked_clients": [3, 4],
+            "deposit_velocity": { "max_deposits": 10, "window_txns": 100 }
+        }"#;
+        let limits: PolicyLimits = serde_json::from_str(raw).unwrap();
+        assert_eq!(limits.max_withdrawal, Some(100_5000));
+        assert_eq!(limits.policies().len(), 3);
+
+        let json = serde_json::to_string(&limits).unwrap();
+        assert!(json.contains(r#""max_withdrawal":"100.5000""#));
+
+        assert!(PolicyLimits::default().policies().is_empty());
+    }
+}
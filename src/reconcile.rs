@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::Account,
+    events::ProcessEvent,
+    ledger::Ledger,
+    record::{decimal, format_amount},
+};
+
+/// An account as it appears in an accounts csv, the
+/// format the engine prints its accounts in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountRow {
+    pub client: u16,
+    #[serde(with = "decimal")]
+    pub available: u128,
+    #[serde(with = "decimal")]
+    pub held: u128,
+    #[serde(with = "decimal")]
+    pub total: u128,
+    pub locked: bool,
+}
+
+impl AccountRow {
+    /// The row of a client's account.
+    pub fn new(client: u16, account: &Account) -> Result<Self, ProcessEvent> {
+        Ok(Self {
+            client,
+            available: account.available,
+            held: account.held,
+            total: account.total()?,
+            locked: account.frozen,
+        })
+    }
+}
+
+/// Read every row of an accounts csv.
+pub fn read_accounts<R: Read>(reader: R) -> Result<Vec<AccountRow>, ProcessEvent> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    reader
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(|err| ProcessEvent::ExternalErr(format!("invalid accounts csv: {err}")))
+}
+
+/// How far each balance may be from the expected balance
+/// and still reconcile.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tolerances {
+    pub available: u128,
+    pub held: u128,
+    pub total: u128,
+}
+
+impl Tolerances {
+    /// The same tolerance for every balance.
+    pub fn uniform(amount: u128) -> Self {
+        Self {
+            available: amount,
+            held: amount,
+            total: amount,
+        }
+    }
+}
+
+/// A balance of an account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Available,
+    Held,
+    Total,
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Field::Available => write!(f, "available"),
+            Field::Held => write!(f, "held"),
+            Field::Total => write!(f, "total"),
+        }
+    }
+}
+
+/// A way an account differs from the expected account.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// the client is expected but has no account.
+    Missing { client: u16 },
+    /// the client has an account but is not expected.
+    Unexpected { client: u16 },
+    /// a balance is further from the expected balance than the tolerance.
+    Balance {
+        client: u16,
+        field: Field,
+        expected: u128,
+        actual: u128,
+    },
+    /// the account is locked when it should not be, or the other way round.
+    Locked {
+        client: u16,
+        expected: bool,
+        actual: bool,
+    },
+}
+
+impl Difference {
+    pub fn client(&self) -> u16 {
+        match *self {
+            Difference::Missing { client }
+            | Difference::Unexpected { client }
+            | Difference::Balance { client, .. }
+            | Difference::Locked { client, .. } => client,
+        }
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difference::Missing { client } => write!(f, "client {client}: no account"),
+            Difference::Unexpected { client } => write!(f, "client {client}: not expected"),
+            Difference::Balance {
+                client,
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "client {client}: {field} is {}, expected {}",
+                format_amount(*actual),
+                format_amount(*expected)
+            ),
+            Difference::Locked {
+                client,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "client {client}: locked is {actual}, expected {expected}"
+            ),
+        }
+    }
+}
+
+/// The outcome of reconciling a ledger against expected accounts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconcileReport {
+    /// number of clients which are expected or have an account.
+    pub clients: usize,
+    /// in client order.
+    pub differences: Vec<Difference>,
+}
+
+impl ReconcileReport {
+    pub fn matched(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl Display for ReconcileReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.matched() {
+            return write!(f, "reconciled {} accounts", self.clients);
+        }
+        write!(
+            f,
+            "reconciliation found {} differences in {} accounts",
+            self.differences.len(),
+            self.clients
+        )?;
+        for difference in &self.differences {
+            write!(f, "\n  {difference}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the accounts of `ledger` against `expected`.
+///
+/// Balances reconcile when they are within `tolerances` of the
+/// expected balance, the locked flag must match exactly. Every
+/// account must be expected and every expected client must have
+/// an account.
+pub fn reconcile(
+    ledger: &Ledger,
+    expected: &[AccountRow],
+    tolerances: &Tolerances,
+) -> Result<ReconcileReport, ProcessEvent> {
+    let mut rows = BTreeMap::new();
+    for row in expected {
+        if rows.insert(row.client, row).is_some() {
+            return Err(ProcessEvent::ExternalErr(format!(
+                "client {} is expected more than once",
+                row.client
+            )));
+        }
+    }
+
+    let mut differences = Vec::new();
+    let mut clients = rows.len();
+    for (client, _) in ledger.accounts().iter() {
+        if !rows.contains_key(&client) {
+            clients += 1;
+            differences.push(Difference::Unexpected { client });
+        }
+    }
+    for (&client, row) in &rows {
+        let Some(account) = ledger.account(client) else {
+            differences.push(Difference::Missing { client });
+            continue;
+        };
+        let balances = [
+            (Field::Available, row.available, account.available),
+            (Field::Held, row.held, account.held),
+            (Field::Total, row.total, account.total()?),
+        ];
+        for (field, expected, actual) in balances {
+            let tolerance = match field {
+                Field::Available => tolerances.available,
+                Field::Held => tolerances.held,
+                Field::Total => tolerances.total,
+            };
+            if expected.abs_diff(actual) > tolerance {
+                differences.push(Difference::Balance {
+                    client,
+                    field,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        if row.locked != account.frozen {
+            differences.push(Difference::Locked {
+                client,
+                expected: row.locked,
+                actual: account.frozen,
+            });
+        }
+    }
+    differences.sort_by_key(Difference::client);
+
+    Ok(ReconcileReport {
+        clients,
+        differences,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{events::ProcessEvent, ledger::Ledger, transaction::Txn};
+
+    use super::{read_accounts, reconcile, Difference, Field, Tolerances};
+
+    #[test]
+    fn test_reconcile() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::new();
+        let txns = [
+            Txn::Deposit {
+                client_id: 1,
+                txn_id: 1,
+                amount: 1_5000,
+            },
+            Txn::Deposit {
+                client_id: 2,
+                txn_id: 2,
+                amount: 7_0000,
+            },
+            Txn::Dispute {
+                client_id: 2,
+                txn_id: 2,
+            },
+            Txn::Deposit {
+                client_id: 3,
+                txn_id: 3,
+                amount: 1_0000,
+            },
+        ];
+        for txn in txns {
+            ledger.process_txn(txn)?;
+        }
+
+        let mut printed = Vec::new();
+        ledger.write_accounts(&mut printed)?;
+        let expected = read_accounts(printed.as_slice())?;
+        let report = reconcile(&ledger, &expected, &Tolerances::default())?;
+        assert!(report.matched());
+        assert_eq!(report.clients, 3);
+
+        let expected = read_accounts(
+            "client,available,held,total,locked
+1,1.5001,0.0000,1.5001,false
+2,0.0000,7.0000,7.0000,true
+4,1.0000,0.0000,1.0000,false
+"
+            .as_bytes(),
+        )?;
+        let report = reconcile(&ledger, &expected, &Tolerances::uniform(1))?;
+        assert!(!report.matched());
+        assert_eq!(report.clients, 4);
+        assert_eq!(
+            report.differences,
+            vec![
+                Difference::Locked {
+                    client: 2,
+                    expected: true,
+                    actual: false
+                },
+                Difference::Unexpected { client: 3 },
+                Difference::Missing { client: 4 },
+            ]
+        );
+
+        let report = reconcile(&ledger, &expected, &Tolerances::default())?;
+        assert_eq!(
+            report.differences[0],
+            Difference::Balance {
+                client: 1,
+                field: Field::Available,
+                expected: 1_5001,
+                actual: 1_5000
+            }
+        );
+        assert_eq!(report.differences.len(), 5);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{de::Error, Deserialize, Deserializer};
 
+/// A row of the transactions csv as it was deserialised.
 #[derive(Debug, Deserialize)]
 pub struct Record {
     #[serde(rename = "type")]
@@ -10,11 +11,21 @@ pub struct Record {
     pub amount: Option<u128>,
 }
 
+/// Deserialise a decimal string with up to 4 decimal places
+/// into a u128 in units of 0.0001.
 pub fn amount_from_string<'de, D>(deserializer: D) -> Result<Option<u128>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s: &str = Deserialize::deserialize(deserializer)?;
+    parse_amount(s).map_err(Error::custom)
+}
+
+/// Parse a decimal string with up to 4 decimal places
+/// into a u128 in units of 0.0001.
+///
+/// An empty string is no amount.
+pub fn parse_amount(s: &str) -> Result<Option<u128>, String> {
     if s.is_empty() {
         return Ok(None);
     };
@@ -26,14 +37,12 @@ where
     if processed.len() == 1 {
         let parsed = s.parse::<u128>();
         if parsed.is_err() {
-            return Err(Error::custom("failed to parse decimal"));
+            return Err("failed to parse decimal".to_owned());
         };
 
         return match parsed.unwrap().checked_mul(10000u128) {
             Some(val) => Ok(Some(val)),
-            None => Err(Error::custom(format!(
-                "failed to parse decimal: limit exceeded"
-            ))),
+            None => Err("failed to parse decimal: limit exceeded".to_owned()),
         };
     }
 
@@ -46,17 +55,73 @@ where
 
         return match format!("{before_point}{char0}{char1}{char2}{char3}").parse::<u128>() {
             Ok(val) => Ok(Some(val)),
-            Err(e) => Err(Error::custom(format!("failed to parse decimal: {e:?}"))),
+            Err(e) => Err(format!("failed to parse decimal: {e:?}")),
         };
     }
 
-    Err(Error::custom(String::from("failed to parse decimal")))
+    Err(String::from("failed to parse decimal"))
+}
+
+/// An amount in units of 0.0001 as a decimal string with 4
+/// decimal places, for reports.
+pub(crate) fn format_amount(amount: u128) -> String {
+    format!("{}.{:04}", amount / 10_000, amount % 10_000)
+}
+
+/// (de)serialise an amount as a decimal string, for amounts
+/// in events and reports.
+pub mod decimal {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::transaction::Txn;
+
+    pub fn serialize<S: Serializer>(amount: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        let decimal = Txn::u128_to_decimal_str(*amount).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&decimal)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        let decimal = String::deserialize(deserializer)?;
+        super::parse_amount(&decimal)
+            .map_err(Error::custom)?
+            .ok_or_else(|| Error::custom("missing amount"))
+    }
+}
+
+/// (de)serialise an optional amount as a decimal string, for
+/// amounts in config files and reports.
+pub mod optional_decimal {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::transaction::Txn;
+
+    pub fn serialize<S: Serializer>(
+        amount: &Option<u128>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match amount {
+            Some(amount) => {
+                let decimal =
+                    Txn::u128_to_decimal_str(*amount).map_err(serde::ser::Error::custom)?;
+                serializer.serialize_some(&decimal)
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u128>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(decimal) => super::parse_amount(&decimal).map_err(Error::custom),
+            None => Ok(None),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::record::Record;
-    use serde_json;
 
     // we use serde_json instead of parsing a csv just for testing as
     // we can use a simple json string.
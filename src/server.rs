@@ -0,0 +1,303 @@
+//! A tcp server applying records from many connections to one ledger.
+//!
+//! Clients send one request per line and get one reply per line.
+//! A request is either a record, as a csv row without a header or
+//! as a json object, or a balance query:
+//!
+//! | request                                                      | reply                                        |
+//! |--------------------------------------------------------------|----------------------------------------------|
+//! | `deposit,1,1,1.5`                                            | `applied`, `rejected: insufficient funds`... |
+//! | `{"type":"deposit","client":1,"tx":1,"amount":"1.5"}`        | `{"outcome":"applied"}`...                   |
+//! | `balance 1`                                                  | `1,1.5000,0.0000,1.5000,false`               |
+//! | `{"query":"balance","client":1}`                             | `{"client":1,"available":"1.5000",...}`      |
+//!
+//! Replies to json requests are json. A request which can not be
+//! understood, or a client without an account, gets an `error: ...`
+//! reply, or `{"error":"..."}`, and the connection stays open.
+//! Records are applied in the order they arrive, whichever
+//! connection they arrive on.
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::ProcessEvent,
+    ledger::Ledger,
+    reconcile::AccountRow,
+    record::{format_amount, Record},
+    transaction::Txn,
+};
+
+/// A request sent as json.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonRequest {
+    Query { query: Query, client: u16 },
+    Record(Record),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Query {
+    Balance,
+}
+
+/// The outcome of a record, as a json reply.
+#[derive(Serialize)]
+struct OutcomeReply {
+    outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ErrorReply {
+    error: String,
+}
+
+/// Accepts connections and applies their records to a shared ledger.
+pub struct Server {
+    listener: TcpListener,
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl Server {
+    pub fn bind(addr: impl ToSocketAddrs, ledger: Ledger) -> Result<Self, ProcessEvent> {
+        let listener = TcpListener::bind(addr).map_err(io_err)?;
+        Ok(Self {
+            listener,
+            ledger: Arc::new(Mutex::new(ledger)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, ProcessEvent> {
+        self.listener.local_addr().map_err(io_err)
+    }
+
+    /// The ledger the server applies records to.
+    pub fn ledger(&self) -> Arc<Mutex<Ledger>> {
+        self.ledger.clone()
+    }
+
+    /// Accept connections until the listener fails, serving
+    /// each connection on its own thread.
+    pub fn run(self) -> Result<(), ProcessEvent> {
+        for stream in self.listener.incoming() {
+            let stream = stream.map_err(io_err)?;
+            let ledger = self.ledger.clone();
+            thread::spawn(move || serve_connection(stream, &ledger));
+        }
+        Ok(())
+    }
+}
+
+fn io_err(err: std::io::Error) -> ProcessEvent {
+    ProcessEvent::ExternalErr(err.to_string())
+}
+
+/// Reply to every line of a connection until it closes.
+fn serve_connection(stream: TcpStream, ledger: &Mutex<Ledger>) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        // one write per reply, so a small reply is not held back
+        // waiting for the client to acknowledge the last.
+        let reply = respond(ledger, &line) + "\n";
+        if writer.write_all(reply.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// The reply to a single request line.
+pub fn respond(ledger: &Mutex<Ledger>, line: &str) -> String {
+    let line = line.trim();
+    if line.starts_with('{') {
+        respond_json(ledger, line)
+    } else {
+        respond_text(ledger, line)
+    }
+}
+
+fn respond_text(ledger: &Mutex<Ledger>, line: &str) -> String {
+    if let Some(client) = line.strip_prefix("balance ") {
+        let row = client
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid client {client}"))
+            .and_then(|client| balance(ledger, client));
+        return match row {
+            Ok(row) => format!(
+                "{},{},{},{},{}",
+                row.client,
+                format_amount(row.available),
+                format_amount(row.held),
+                format_amount(row.total),
+                row.locked
+            ),
+            Err(err) => format!("error: {err}"),
+        };
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .from_reader(line.as_bytes());
+    let record = match reader.deserialize::<Record>().next() {
+        Some(Ok(record)) => record,
+        Some(Err(err)) => return format!("error: invalid record: {err}"),
+        None => return "error: empty record".to_owned(),
+    };
+    match apply(ledger, record) {
+        Ok(ProcessEvent::ProcessComplete) => "applied".to_owned(),
+        Ok(ProcessEvent::Flagged(reason)) => format!("flagged: {reason}"),
+        Ok(ProcessEvent::Rejected(reason)) => format!("rejected: {reason}"),
+        Ok(err @ ProcessEvent::ExternalErr(_)) | Err(err) => format!("error: {err}"),
+    }
+}
+
+fn respond_json(ledger: &Mutex<Ledger>, line: &str) -> String {
+    let to_json = |reply: Result<String, serde_json::Error>| {
+        reply.unwrap_or_else(|err| format!(r#"{{"error":"{err}"}}"#))
+    };
+    let error = |error: String| to_json(serde_json::to_string(&ErrorReply { error }));
+
+    let record = match serde_json::from_str(line) {
+        Ok(JsonRequest::Query {
+            query: Query::Balance,
+            client,
+        }) => {
+            return match balance(ledger, client) {
+                Ok(row) => to_json(serde_json::to_string(&row)),
+                Err(err) => error(err),
+            };
+        }
+        Ok(JsonRequest::Record(record)) => record,
+        Err(err) => return error(format!("invalid request: {err}")),
+    };
+    let (outcome, reason) = match apply(ledger, record) {
+        Ok(ProcessEvent::ProcessComplete) => ("applied", None),
+        Ok(ProcessEvent::Flagged(reason)) => ("flagged", Some(reason)),
+        Ok(ProcessEvent::Rejected(reason)) => ("rejected", Some(reason.to_string())),
+        Ok(err @ ProcessEvent::ExternalErr(_)) | Err(err) => return error(err.to_string()),
+    };
+    to_json(serde_json::to_string(&OutcomeReply { outcome, reason }))
+}
+
+fn apply(ledger: &Mutex<Ledger>, record: Record) -> Result<ProcessEvent, ProcessEvent> {
+    let txn = Txn::from_record(record)?;
+    let mut ledger = ledger
+        .lock()
+        .map_err(|_| ProcessEvent::ExternalErr("ledger poisoned".to_owned()))?;
+    ledger.process_txn(txn)
+}
+
+fn balance(ledger: &Mutex<Ledger>, client: u16) -> Result<AccountRow, String> {
+    let ledger = ledger.lock().map_err(|_| "ledger poisoned".to_owned())?;
+    let account = ledger
+        .account(client)
+        .ok_or_else(|| format!("no account for client {client}"))?;
+    Ok(AccountRow {
+        client,
+        available: account.available,
+        held: account.held,
+        total: account.total().map_err(|err| err.to_string())?,
+        locked: account.frozen,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    use crate::{events::ProcessEvent, ledger::Ledger};
+
+    use super::Server;
+
+    /// send each request on its own line and read a reply line for each.
+    fn exchange(stream: &TcpStream, requests: &[&str]) -> Vec<String> {
+        let mut writer = stream;
+        let mut reader = BufReader::new(stream);
+        requests
+            .iter()
+            .map(|request| {
+                writer.write_all(format!("{request}\n").as_bytes()).unwrap();
+                let mut reply = String::new();
+                reader.read_line(&mut reply).unwrap();
+                reply.trim_end().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_server() -> Result<(), ProcessEvent> {
+        let server = Server::bind("127.0.0.1:0", Ledger::new())?;
+        let addr = server.local_addr()?;
+        let ledger = server.ledger();
+        thread::spawn(move || server.run());
+
+        // many clients depositing at once
+        let clients: Vec<_> = (1..=8u32)
+            .map(|client| {
+                thread::spawn(move || {
+                    let stream = TcpStream::connect(addr).unwrap();
+                    let requests: Vec<String> = (0..50)
+                        .map(|n| format!("deposit,{client},{},1.0", client * 1000 + n))
+                        .collect();
+                    let requests: Vec<&str> = requests.iter().map(String::as_str).collect();
+                    exchange(&stream, &requests)
+                })
+            })
+            .collect();
+        for client in clients {
+            let replies = client.join().unwrap();
+            assert!(replies.iter().all(|reply| reply == "applied"));
+        }
+        assert_eq!(ledger.lock().unwrap().stats().total(), 400);
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let replies = exchange(
+            &stream,
+            &[
+                "withdrawal,1,1,100.0",
+                "dispute, 2, 2000,",
+                "balance 2",
+                r#"{"type":"withdrawal","client":3,"tx":2,"amount":"10.5"}"#,
+                r#"{"type":"withdrawal","client":3,"tx":3,"amount":"100"}"#,
+                r#"{"query":"balance","client":3}"#,
+                "balance 99",
+                "refund,1,1,1.0",
+                r#"{"query":"refund"}"#,
+            ],
+        );
+        assert_eq!(
+            replies,
+            vec![
+                "rejected: insufficient funds",
+                "applied",
+                "2,49.0000,1.0000,50.0000,false",
+                r#"{"outcome":"applied"}"#,
+                r#"{"outcome":"rejected","reason":"insufficient funds"}"#,
+                r#"{"client":3,"available":"39.5000","held":"0.0000","total":"39.5000","locked":false}"#,
+                "error: no account for client 99",
+                "error: unrecognised txn: refund",
+                replies[8].as_str(),
+            ]
+        );
+        assert!(replies[8].starts_with(r#"{"error":"invalid request"#));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use std::error::Error;
+use std::io::Read;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+
+use crate::{
+    events::ProcessEvent,
+    ledger::Ledger,
+    record::Record,
+    transaction::{Txn, TxnIds},
+};
+
+// records are sent to workers in batches to keep
+// channel overhead off the per record cost.
+const BATCH_SIZE: usize = 1024;
+const BATCHES_IN_FLIGHT: usize = 16;
+
+/// Transactions with their position in the input, and whether a
+/// deposit or withdrawal reuses an id first used on another shard.
+type Batch = Vec<(u64, Txn, bool)>;
+
+/// Process a csv of transactions over `shards` worker ledgers.
+///
+/// The calling thread reads the csv and routes each record to the
+/// worker which owns the affected account, so every account is only
+/// touched by one thread and sees its transactions in input order.
+/// The shard ledgers are merged once the input is exhausted, so the
+/// result is the same as processing the file on a single ledger.
+///
+/// Each worker ledger is built with `new_ledger`.
+pub fn process_sharded<R: Read>(
+    reader: &mut csv::Reader<R>,
+    shards: usize,
+    new_ledger: impl Fn() -> Ledger + Sync,
+) -> Result<Ledger, Box<dyn Error>> {
+    let shards = shards.max(1);
+    let new_ledger = &new_ledger;
+
+    thread::scope(|scope| {
+        let mut senders = Vec::with_capacity(shards);
+        let mut workers = Vec::with_capacity(shards);
+        for _ in 0..shards {
+            let (sender, receiver) = mpsc::sync_channel::<Batch>(BATCHES_IN_FLIGHT);
+            senders.push(sender);
+            workers.push(scope.spawn(move || -> Result<Ledger, ProcessEvent> {
+                let mut ledger = new_ledger();
+                for batch in receiver {
+                    for (seq, txn, reused) in batch {
+                        if reused {
+                            ledger.spend_txn_id(txn.txn_id());
+                        }
+                        ledger.process_txn_at(seq, txn)?;
+                    }
+                }
+                Ok(ledger)
+            }));
+        }
+
+        let routed = route(reader, senders);
+        let ledgers = workers
+            .into_iter()
+            .map(|worker| worker.join().expect("shard worker panicked"))
+            .collect::<Result<Vec<_>, _>>()?;
+        routed?;
+
+        Ok(Ledger::merge(ledgers)?)
+    })
+}
+
+/// Read every record and send it to the shard owning its account.
+///
+/// Deposits and withdrawals belong to the shard of their client.
+/// Disputes, resolves and chargebacks belong to the shard of the
+/// client who first used the referenced id, which may not be the
+/// client on the record.
+///
+/// A deposit or withdrawal reusing an id is sent to its client's
+/// shard marked as reused, so the shard rejects it as a duplicate
+/// as a single ledger would.
+fn route<R: Read>(
+    reader: &mut csv::Reader<R>,
+    senders: Vec<SyncSender<Batch>>,
+) -> Result<(), Box<dyn Error>> {
+    let shards = senders.len();
+    let mut batches: Vec<Batch> = (0..shards)
+        .map(|_| Vec::with_capacity(BATCH_SIZE))
+        .collect();
+
+    // every id used by a deposit or withdrawal, and the ids first
+    // used on each shard, a bit per id.
+    let mut seen = TxnIds::default();
+    let mut owners: Vec<TxnIds> = (0..shards).map(|_| TxnIds::default()).collect();
+
+    for (index, result) in reader.deserialize().enumerate() {
+        let record: Record = result?;
+        let txn = Txn::from_record(record)?;
+        let txn_id = txn.txn_id();
+        let mut reused = false;
+        let shard = match txn {
+            Txn::Deposit { client_id, .. } | Txn::Withdraw { client_id, .. } => {
+                let shard = client_id as usize % shards;
+                reused = seen.contains(txn_id);
+                if !reused {
+                    seen.insert(txn_id);
+                    owners[shard].insert(txn_id);
+                }
+                shard
+            }
+            _ => owners
+                .iter()
+                .position(|owned| owned.contains(txn_id))
+                .unwrap_or(txn.client_id() as usize % shards),
+        };
+
+        batches[shard].push((index as u64 + 1, txn, reused));
+        if batches[shard].len() == BATCH_SIZE {
+            let batch = std::mem::replace(&mut batches[shard], Vec::with_capacity(BATCH_SIZE));
+            // a worker only hangs up after a failure, which it reports.
+            if senders[shard].send(batch).is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    for (sender, batch) in senders.iter().zip(batches) {
+        if !batch.is_empty() && sender.send(batch).is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use crate::ledger::{DisputeWindow, Ledger};
+    use crate::record::Record;
+
+    use super::process_sharded;
+
+    // deposits, withdrawals and references for 10 clients,
+    // with references made by a different client to the depositor.
+    fn transactions() -> String {
+        let mut csv = String::from("type, client, tx, amount\n");
+        for tx in 1..=2000u32 {
+            let client = tx % 10;
+            let line = match tx % 7 {
+                // reuse the id of an earlier record, of another client
+                _ if tx % 11 == 0 => format!("deposit, {}, {}, 1.0\n", (client + 5) % 10, tx - 5),
+                0 => format!("withdrawal, {client}, {tx}, 1.5\n"),
+                3 => format!("dispute, {}, {}, \n", (client + 1) % 10, tx - 1),
+                5 if tx % 2 == 0 => format!("resolve, {}, {}, \n", (client + 3) % 10, tx - 3),
+                6 if tx % 4 == 0 => format!("chargeback, {client}, {}, \n", tx - 4),
+                _ => format!("deposit, {client}, {tx}, {}.{}\n", tx % 13, tx % 7),
+            };
+            csv.push_str(&line);
+        }
+        csv
+    }
+
+    fn reader(csv: &str) -> csv::Reader<&[u8]> {
+        csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(csv.as_bytes())
+    }
+
+    fn output(ledger: &Ledger) -> String {
+        let mut out = Vec::new();
+        ledger.write_accounts(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_sharded_matches_single_ledger() -> Result<(), Box<dyn Error>> {
+        let csv = transactions();
+        for window in [DisputeWindow::Unbounded, DisputeWindow::Transactions(20)] {
+            let mut single = Ledger::with_dispute_window(window);
+            for result in reader(&csv).deserialize() {
+                let record: Record = result?;
+                single.process_transaction(record)?;
+            }
+            // make sure the input exercises frozen accounts
+            assert!(single.accounts.iter().any(|(_, account)| account.frozen));
+
+            for shards in 1..=4 {
+                let sharded = process_sharded(&mut reader(&csv), shards, || {
+                    Ledger::with_dispute_window(window)
+                })?;
+                assert_eq!(output(&sharded), output(&single));
+                assert_eq!(sharded.txn_history.len(), single.txn_history.len());
+                assert_eq!(sharded.stats().transactions, single.stats().transactions);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharded_reused_id() -> Result<(), Box<dyn Error>> {
+        // the reused id is rejected, and the dispute goes to the
+        // client who first used it
+        let csv = "type, client, tx, amount
+deposit, 1, 1, 5.0
+deposit, 2, 1, 7.0
+dispute, 1, 1,
+";
+        let mut single = Ledger::new();
+        for result in reader(csv).deserialize() {
+            let record: Record = result?;
+            single.process_transaction(record)?;
+        }
+        assert_eq!(single.accounts.get(&1).unwrap().held, 5_0000);
+        for shards in 1..=2 {
+            let sharded = process_sharded(&mut reader(csv), shards, Ledger::new)?;
+            assert_eq!(output(&sharded), output(&single));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharded_malformed_record() {
+        let csv = "type, client, tx, amount\ndeposit, 1, 1, 1.0\nbogus, 2, 2, 1.0\n";
+        let result = process_sharded(&mut reader(csv), 2, Ledger::new);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use crate::{
+    events::ProcessEvent,
+    ledger::Ledger,
+    merkle::{Hash, MerkleTree},
+    snapshot::AccountSnapshot,
+    transaction::Txn,
+};
+
+/// The accounts of a ledger as they were between two writes.
+///
+/// A view never changes once published, so it can be read for as
+/// long as it is held while the ledger moves on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerView {
+    seq: u64,
+    // in client id order.
+    accounts: Vec<AccountSnapshot>,
+}
+
+impl LedgerView {
+    fn new(ledger: &Ledger) -> Self {
+        Self {
+            seq: ledger.seq(),
+            accounts: ledger.snapshot_accounts(),
+        }
+    }
+
+    /// Sequence number of the last transaction the view includes.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn account(&self, client: u16) -> Option<&AccountSnapshot> {
+        self.accounts
+            .binary_search_by_key(&client, |account| account.client)
+            .ok()
+            .map(|index| &self.accounts[index])
+    }
+
+    /// Every account, in client id order.
+    pub fn accounts(&self) -> &[AccountSnapshot] {
+        &self.accounts
+    }
+
+    /// The [`Ledger::state_hash`] of the accounts in the view.
+    pub fn state_hash(&self) -> Hash {
+        MerkleTree::from_accounts(self.accounts.clone()).root()
+    }
+}
+
+/// A ledger shared between threads, with one writer at a time and
+/// any number of readers.
+///
+/// Readers take the latest [`LedgerView`] and are never blocked by a
+/// write in progress: a write applies its transactions to the ledger,
+/// then publishes a new view of the accounts in one step. A reader
+/// sees every transaction of a write, or none of them. Publishing
+/// copies the accounts, so transactions applied in batches cost
+/// less than one at a time.
+pub struct SharedLedger {
+    ledger: Mutex<Ledger>,
+    view: RwLock<Arc<LedgerView>>,
+}
+
+impl SharedLedger {
+    pub fn new(ledger: Ledger) -> Self {
+        let view = Arc::new(LedgerView::new(&ledger));
+        Self {
+            ledger: Mutex::new(ledger),
+            view: RwLock::new(view),
+        }
+    }
+
+    /// The accounts as of the last completed write.
+    pub fn view(&self) -> Arc<LedgerView> {
+        // the lock only guards swapping the view, which can not
+        // be left half done, so a poisoned lock is still sound.
+        self.view
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Run `write` against the ledger, then publish its accounts,
+    /// whatever `write` returned.
+    pub fn write<T>(&self, write: impl FnOnce(&mut Ledger) -> T) -> Result<T, ProcessEvent> {
+        let mut ledger = self
+            .ledger
+            .lock()
+            .map_err(|_| ProcessEvent::ExternalErr("ledger poisoned".to_owned()))?;
+        let result = write(&mut ledger);
+        let view = Arc::new(LedgerView::new(&ledger));
+        *self.view.write().unwrap_or_else(PoisonError::into_inner) = view;
+        Ok(result)
+    }
+
+    /// Apply a transaction and publish it.
+    pub fn process_txn(&self, txn: Txn) -> Result<ProcessEvent, ProcessEvent> {
+        self.write(|ledger| ledger.process_txn(txn))?
+    }
+
+    /// Apply transactions in order and publish them together, with
+    /// the outcome of each.
+    ///
+    /// An error stops the batch, and the transactions before it are
+    /// published.
+    pub fn process_batch(
+        &self,
+        txns: impl IntoIterator<Item = Txn>,
+    ) -> Result<Vec<ProcessEvent>, ProcessEvent> {
+        self.write(|ledger| {
+            txns.into_iter()
+                .map(|txn| ledger.process_txn(txn))
+                .collect()
+        })?
+    }
+
+    /// The ledger, once no other thread shares it.
+    pub fn into_inner(self) -> Ledger {
+        self.ledger
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::{events::ProcessEvent, ledger::Ledger, transaction::Txn};
+
+    use super::SharedLedger;
+
+    const CLIENTS: u16 = 16;
+    const BATCHES: u32 = 200;
+
+    #[test]
+    fn test_shared_ledger() -> Result<(), ProcessEvent> {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SharedLedger>();
+
+        let shared = Arc::new(SharedLedger::new(Ledger::new()));
+
+        // every batch deposits 1 to each client, so a consistent
+        // view has the same balance in every account.
+        let writer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for batch in 0..BATCHES {
+                    let txns = (0..CLIENTS).map(|client| Txn::Deposit {
+                        client_id: client,
+                        txn_id: batch * CLIENTS as u32 + client as u32,
+                        amount: 1_0000,
+                    });
+                    shared.process_batch(txns).unwrap();
+                }
+            })
+        };
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let mut last_seq = 0;
+                    while last_seq < (BATCHES * CLIENTS as u32) as u64 {
+                        let view = shared.view();
+                        assert!(view.seq() >= last_seq);
+                        assert_eq!(view.seq() % CLIENTS as u64, 0);
+                        let balance = view.seq() / CLIENTS as u64 * 1_0000;
+                        if view.seq() > 0 {
+                            assert_eq!(view.accounts().len(), CLIENTS as usize);
+                        }
+                        for account in view.accounts() {
+                            assert_eq!(account.available, balance as u128);
+                        }
+                        last_seq = view.seq();
+                    }
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        // a reader is not blocked by a write in progress
+        let (sent, received) = mpsc::channel();
+        let (start, received_start) = mpsc::channel();
+        let reader = shared.clone();
+        let reader = thread::spawn(move || {
+            // read once the write has started
+            received_start.recv().unwrap();
+            sent.send(reader.view()).unwrap();
+        });
+        let view = shared.write(|ledger| {
+            start.send(()).unwrap();
+            ledger.process_txn(Txn::Withdraw {
+                client_id: 0,
+                txn_id: u32::MAX,
+                amount: 1_0000,
+            })?;
+            received
+                .recv_timeout(Duration::from_secs(10))
+                .map_err(|err| ProcessEvent::ExternalErr(err.to_string()))
+        })??;
+        reader.join().unwrap();
+        assert_eq!(view.account(0).unwrap().available, 200_0000);
+        assert_eq!(shared.view().account(0).unwrap().available, 199_0000);
+
+        let shared = Arc::into_inner(shared).unwrap();
+        let view = shared.view();
+        let ledger = shared.into_inner();
+        assert_eq!(view.state_hash(), ledger.state_hash());
+
+        Ok(())
+    }
+}
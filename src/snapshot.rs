@@ -0,0 +1,268 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    books::Books,
+    config::LedgerConfig,
+    events::ProcessEvent,
+    ledger::Ledger,
+    observer::{DomainEvent, EventRecord},
+    record::decimal,
+    transaction::{TxnKind, TxnState},
+};
+
+/// The state of an account in a snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    pub client: u16,
+    #[serde(with = "decimal")]
+    pub available: u128,
+    #[serde(with = "decimal")]
+    pub held: u128,
+    /// ids of the transactions under dispute, in id order.
+    pub disputes: Vec<u32>,
+    pub frozen: bool,
+}
+
+/// A history entry in a snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistorySnapshot {
+    pub tx: u32,
+    pub client: u16,
+    pub kind: TxnKind,
+    pub state: TxnState,
+    #[serde(with = "decimal")]
+    pub amount: u128,
+    pub seq: u64,
+    pub expired: bool,
+}
+
+/// The state of a ledger after the `seq`th transaction.
+///
+/// `journal_offset` is the length of the events journal at that
+/// point, so the ledger can be brought forward from the snapshot by
+/// replaying the journal from there. Policies are not part of a
+/// snapshot, they start afresh in a ledger restored from one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub seq: u64,
+    pub journal_offset: u64,
+    pub config: LedgerConfig,
+    /// in client id order.
+    pub accounts: Vec<AccountSnapshot>,
+    /// in txn id order.
+    pub history: Vec<HistorySnapshot>,
+    /// ids of transactions evicted from the history, in id order.
+    pub expired: Vec<u32>,
+    /// ids of withdrawals and rejected deposits not kept in the
+    /// history, in id order, which can not be reused.
+    #[serde(default)]
+    pub spent: Vec<u32>,
+    pub books: Books,
+}
+
+impl Snapshot {
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, ProcessEvent> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("failed to open {}: {err}", path.display()))
+        })?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("invalid snapshot {}: {err}", path.display()))
+        })
+    }
+
+    pub fn write_json_file(&self, path: impl AsRef<Path>) -> Result<(), ProcessEvent> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec(self).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("failed to serialise snapshot: {err}"))
+        })?;
+        fs::write(path, json).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("failed to write {}: {err}", path.display()))
+        })
+    }
+}
+
+/// A directory of snapshots, one file per snapshot named by its seq.
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    /// Use `dir` for snapshots, creating it if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, ProcessEvent> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("failed to create {}: {err}", dir.display()))
+        })?;
+        Ok(Self { dir })
+    }
+
+    pub fn save(&self, snapshot: &Snapshot) -> Result<(), ProcessEvent> {
+        snapshot.write_json_file(self.dir.join(format!("snapshot-{:020}.json", snapshot.seq)))
+    }
+
+    /// The seq of every snapshot in the store, in order.
+    pub fn seqs(&self) -> Result<Vec<u64>, ProcessEvent> {
+        let entries = fs::read_dir(&self.dir).map_err(|err| {
+            ProcessEvent::ExternalErr(format!("failed to read {}: {err}", self.dir.display()))
+        })?;
+        let mut seqs: Vec<u64> = entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name();
+                let seq = name.to_str()?.strip_prefix("snapshot-")?;
+                seq.strip_suffix(".json")?.parse().ok()
+            })
+            .collect();
+        seqs.sort_unstable();
+        Ok(seqs)
+    }
+
+    /// The latest snapshot taken at or before `seq`.
+    pub fn latest_at(&self, seq: u64) -> Result<Option<Snapshot>, ProcessEvent> {
+        let Some(latest) = self.seqs()?.into_iter().rev().find(|&s| s <= seq) else {
+            return Ok(None);
+        };
+        let path = self.dir.join(format!("snapshot-{latest:020}.json"));
+        Snapshot::from_json_file(path).map(Some)
+    }
+}
+
+fn io_err(err: std::io::Error) -> ProcessEvent {
+    ProcessEvent::ExternalErr(err.to_string())
+}
+
+/// The ledger as it was after the `seq`th transaction.
+///
+/// Starts from the latest snapshot in `store` at or before `seq`, if
+/// there is one, and replays the rest of the way from the journal at
+/// `journal`. Without a snapshot the ledger is replayed from the
+/// start of the journal with `config`.
+pub fn ledger_at(
+    config: LedgerConfig,
+    journal: impl AsRef<Path>,
+    store: Option<&SnapshotStore>,
+    seq: u64,
+) -> Result<Ledger, ProcessEvent> {
+    let snapshot = match store {
+        Some(store) => store.latest_at(seq)?,
+        None => None,
+    };
+    let (mut ledger, offset) = match snapshot {
+        Some(snapshot) => {
+            let offset = snapshot.journal_offset;
+            (Ledger::from_snapshot(snapshot), offset)
+        }
+        None => (Ledger::with_config(config), 0),
+    };
+
+    let mut journal = File::open(journal).map_err(io_err)?;
+    journal.seek(SeekFrom::Start(offset)).map_err(io_err)?;
+    for line in BufReader::new(journal).lines() {
+        let line = line.map_err(io_err)?;
+        let record: EventRecord = serde_json::from_str(&line)
+            .map_err(|err| ProcessEvent::ExternalErr(format!("invalid event: {err}")))?;
+        if record.seq > seq {
+            break;
+        }
+        ledger.apply_event(&record)?;
+    }
+    Ok(ledger)
+}
+
+/// The seq of the deposit or withdrawal with id `txn_id`, applied
+/// or rejected, from the journal at `journal`.
+///
+/// If the id was used more than once, the seq of the last use.
+pub fn seq_of_txn(journal: impl AsRef<Path>, txn_id: u32) -> Result<Option<u64>, ProcessEvent> {
+    let journal = BufReader::new(File::open(journal).map_err(io_err)?);
+    let mut found = None;
+    for line in journal.lines() {
+        let line = line.map_err(io_err)?;
+        let record: EventRecord = serde_json::from_str(&line)
+            .map_err(|err| ProcessEvent::ExternalErr(format!("invalid event: {err}")))?;
+        let matches = match &record.event {
+            DomainEvent::Deposited { tx, .. } | DomainEvent::Withdrew { tx, .. } => *tx == txn_id,
+            DomainEvent::Rejected { tx, r#type, .. } => {
+                *tx == txn_id && (r#type == "deposit" || r#type == "withdrawal")
+            }
+            _ => false,
+        };
+        if matches {
+            found = Some(record.seq);
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        config::LedgerConfig, events::ProcessEvent, ledger::Ledger, observer::JsonlEventSink,
+        transaction::Txn,
+    };
+
+    use super::{ledger_at, seq_of_txn, SnapshotStore};
+
+    #[test]
+    fn test_ledger_at() -> Result<(), ProcessEvent> {
+        let dir = std::env::temp_dir().join(format!("ledger-at-{}", std::process::id()));
+        let store = SnapshotStore::new(dir.join("snapshots"))?;
+        let journal_path = dir.join("events.jsonl");
+
+        let journal = BufWriter::new(File::create(&journal_path).unwrap());
+        let journal = Arc::new(Mutex::new(JsonlEventSink::new(journal)));
+        let mut ledger = Ledger::builder().observer(journal.clone()).build();
+
+        // the live state after every transaction
+        let mut live = Vec::new();
+        for txn_id in 1..=60u32 {
+            let client_id = (txn_id % 4) as u16;
+            let txn = match txn_id % 5 {
+                0 => Txn::Dispute {
+                    client_id,
+                    txn_id: txn_id - 4,
+                },
+                3 => Txn::Withdraw {
+                    client_id,
+                    txn_id,
+                    amount: 3_0000,
+                },
+                _ => Txn::Deposit {
+                    client_id,
+                    txn_id,
+                    amount: txn_id as u128 * 1_0000,
+                },
+            };
+            ledger.process_txn(txn)?;
+            live.push(ledger.snapshot(0));
+            if txn_id % 25 == 0 {
+                let offset = journal.lock().unwrap().bytes_written();
+                store.save(&ledger.snapshot(offset))?;
+            }
+        }
+        drop(ledger);
+        drop(journal);
+
+        assert_eq!(store.seqs()?, vec![25, 50]);
+        for seq in [1, 24, 25, 26, 49, 50, 60] {
+            let replayed = ledger_at(LedgerConfig::default(), &journal_path, None, seq)?;
+            let restored = ledger_at(LedgerConfig::default(), &journal_path, Some(&store), seq)?;
+            assert_eq!(replayed.snapshot(0), live[seq as usize - 1]);
+            assert_eq!(restored.snapshot(0), live[seq as usize - 1]);
+        }
+
+        assert_eq!(seq_of_txn(&journal_path, 33)?, Some(33));
+        assert_eq!(seq_of_txn(&journal_path, 99)?, None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::io::{BufRead, Write};
+
+use serde::Serialize;
+
+use crate::{
+    account::Account,
+    events::ProcessEvent,
+    observer::{DomainEvent, EventRecord, LedgerObserver},
+    record::{decimal, optional_decimal},
+    transaction::TxnKind,
+};
+
+/// A transaction which affected an account, and the balances after it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementLine {
+    /// position of the transaction in the input.
+    pub seq: u64,
+    pub tx: u32,
+    /// the transaction type, as it appears in the csv.
+    #[serde(rename = "type")]
+    pub r#type: String,
+    /// `applied` or `rejected`.
+    pub outcome: String,
+    /// why the transaction was rejected.
+    pub reason: Option<String>,
+    /// the amount moved, empty if nothing moved.
+    #[serde(with = "optional_decimal")]
+    pub amount: Option<u128>,
+    #[serde(with = "decimal")]
+    pub available: u128,
+    #[serde(with = "decimal")]
+    pub held: u128,
+    #[serde(with = "decimal")]
+    pub total: u128,
+    pub locked: bool,
+}
+
+/// Every transaction affecting a single client's account, with
+/// running balances, built from the ledger's domain events.
+///
+/// Disputes, resolves and chargebacks appear on the statement of the
+/// client who made the referenced transaction, whoever raised them.
+/// Rejected transactions appear on the statement of the client on
+/// the record.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Statement {
+    pub client: u16,
+    pub lines: Vec<StatementLine>,
+    #[serde(skip)]
+    account: Account,
+}
+
+impl Statement {
+    /// An empty statement for `client`, to be built up
+    /// event by event, or as an observer of a ledger.
+    pub fn new(client: u16) -> Self {
+        Self {
+            client,
+            lines: Vec::new(),
+            account: Account::new(),
+        }
+    }
+
+    /// The statement of `client` from a journal of events, one
+    /// json [`EventRecord`] per line, as written by `JsonlEventSink`.
+    pub fn from_journal<R: BufRead>(client: u16, journal: R) -> Result<Self, ProcessEvent> {
+        let mut statement = Self::new(client);
+        for (number, line) in journal.lines().enumerate() {
+            let line = line.map_err(|err| ProcessEvent::ExternalErr(err.to_string()))?;
+            let record: EventRecord = serde_json::from_str(&line).map_err(|err| {
+                ProcessEvent::ExternalErr(format!("invalid event on line {}: {err}", number + 1))
+            })?;
+            statement.push(&record)?;
+        }
+        Ok(statement)
+    }
+
+    /// Add an event to the statement, if it concerns the client.
+    pub fn push(&mut self, record: &EventRecord) -> Result<(), ProcessEvent> {
+        if record.event.client() != self.client {
+            return Ok(());
+        }
+        let account = &mut self.account;
+        let (tx, r#type, amount, reason) = match &record.event {
+            &DomainEvent::Deposited { tx, amount, .. } => {
+                account.add_available(amount)?;
+                (tx, "deposit", Some(amount), None)
+            }
+            &DomainEvent::Withdrew { tx, amount, .. } => {
+                account.sub_available(amount)?;
+                (tx, "withdrawal", Some(amount), None)
+            }
+            &DomainEvent::DisputeOpened {
+                tx, kind, amount, ..
+            } => {
+                if kind == TxnKind::Deposit {
+                    account.sub_available(amount)?;
+                }
+                account.add_held(amount)?;
+                (tx, "dispute", Some(amount), None)
+            }
+            &DomainEvent::Resolved {
+                tx, kind, amount, ..
+            } => {
+                account.sub_held(amount)?;
+                if kind == TxnKind::Deposit {
+                    account.add_available(amount)?;
+                }
+                (tx, "resolve", Some(amount), None)
+            }
+            &DomainEvent::ChargedBack {
+                tx, kind, amount, ..
+            } => {
+                account.sub_held(amount)?;
+                if kind == TxnKind::Withdrawal {
+                    account.add_available(amount)?;
+                }
+                (tx, "chargeback", Some(amount), None)
+            }
+            DomainEvent::AccountFrozen { .. } => {
+                account.freeze();
+                // the freeze belongs to the chargeback before it.
+                if let Some(line) = self.lines.last_mut() {
+                    if line.seq == record.seq {
+                        line.locked = true;
+                    }
+                }
+                return Ok(());
+            }
+            DomainEvent::Rejected {
+                tx, r#type, reason, ..
+            } => (*tx, r#type.as_str(), None, Some(reason.to_string())),
+            DomainEvent::AccountOpened { .. } | DomainEvent::Expired { .. } => return Ok(()),
+        };
+
+        let outcome = if reason.is_some() {
+            "rejected"
+        } else {
+            "applied"
+        };
+        self.lines.push(StatementLine {
+            seq: record.seq,
+            tx,
+            r#type: r#type.to_owned(),
+            outcome: outcome.to_owned(),
+            reason,
+            amount,
+            available: account.available,
+            held: account.held,
+            total: account.total()?,
+            locked: account.frozen,
+        });
+        Ok(())
+    }
+
+    /// Write the lines of the statement as csv.
+    pub fn write_csv<W: Write>(&self, out: W) -> Result<(), ProcessEvent> {
+        let csv_err = |err: csv::Error| ProcessEvent::ExternalErr(err.to_string());
+        let mut writer = csv::Writer::from_writer(out);
+        for line in &self.lines {
+            writer.serialize(line).map_err(csv_err)?;
+        }
+        writer
+            .flush()
+            .map_err(|err| ProcessEvent::ExternalErr(err.to_string()))
+    }
+
+    /// Write the statement as a json object.
+    pub fn write_json<W: Write>(&self, out: W) -> Result<(), ProcessEvent> {
+        serde_json::to_writer_pretty(out, self)
+            .map_err(|err| ProcessEvent::ExternalErr(err.to_string()))
+    }
+}
+
+impl LedgerObserver for Statement {
+    fn on_event(&mut self, seq: u64, event: &DomainEvent) -> Result<(), ProcessEvent> {
+        self.push(&EventRecord {
+            seq,
+            event: event.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{events::ProcessEvent, ledger::Ledger, transaction::Txn};
+
+    use super::Statement;
+
+    #[test]
+    fn test_statement() -> Result<(), ProcessEvent> {
+        let statement = Arc::new(Mutex::new(Statement::new(7)));
+        let mut ledger = Ledger::builder().observer(statement.clone()).build();
+
+        let txns = [
+            Txn::Deposit {
+                client_id: 7,
+                txn_id: 1,
+                amount: 10_0000,
+            },
+            Txn::Deposit {
+                client_id: 3,
+                txn_id: 2,
+                amount: 1_0000,
+            },
+            Txn::Withdraw {
+                client_id: 7,
+                txn_id: 3,
+                amount: 20_0000,
+            },
+            // another client disputes client 7's deposit
+            Txn::Dispute {
+                client_id: 3,
+                txn_id: 1,
+            },
+            Txn::ChargeBack {
+                client_id: 7,
+                txn_id: 1,
+            },
+        ];
+        for txn in txns {
+            ledger.process_txn(txn)?;
+        }
+
+        let statement = statement.lock().unwrap();
+        let mut csv = Vec::new();
+        statement.write_csv(&mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "seq,tx,type,outcome,reason,amount,available,held,total,locked
+1,1,deposit,applied,,10.0000,10.0000,0.0000,10.0000,false
+3,3,withdrawal,rejected,insufficient funds,,10.0000,0.0000,10.0000,false
+4,1,dispute,applied,,10.0000,0.0000,10.0000,10.0000,false
+5,1,chargeback,applied,,10.0000,0.0000,0.0000,0.0000,true
+"
+        );
+
+        let mut json = Vec::new();
+        statement.write_json(&mut json)?;
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["client"], 7);
+        assert_eq!(json["lines"][3]["locked"], true);
+        assert_eq!(json["lines"][1]["reason"], "insufficient funds");
+
+        Ok(())
+    }
+}
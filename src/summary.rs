@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{
+    config::LedgerConfig,
+    ledger::Ledger,
+    merkle::to_hex,
+    observer::DomainEvent,
+    record::{format_amount, optional_decimal},
+};
+
+/// How many transactions of a type were applied, and how
+/// many were rejected for each reason.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TypeCounts {
+    pub applied: u64,
+    pub rejected: BTreeMap<String, u64>,
+}
+
+impl TypeCounts {
+    pub fn total(&self) -> u64 {
+        self.applied + self.rejected.values().sum::<u64>()
+    }
+}
+
+/// Counts of what a ledger has done, kept as its events are applied.
+///
+/// A ledger restored from a snapshot counts from the snapshot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LedgerStats {
+    /// by transaction type, as it appears in the csv.
+    pub transactions: BTreeMap<String, TypeCounts>,
+    /// amount totals, `None` once a total has passed u128::MAX.
+    #[serde(with = "optional_decimal")]
+    pub deposited: Option<u128>,
+    #[serde(with = "optional_decimal")]
+    pub withdrawn: Option<u128>,
+    #[serde(with = "optional_decimal")]
+    pub charged_back: Option<u128>,
+    /// the most transactions kept in the history at once. For
+    /// merged ledgers, the sum of the peaks of the shards, which is
+    /// an upper bound as the shards need not peak together.
+    pub peak_history: usize,
+}
+
+impl Default for LedgerStats {
+    fn default() -> Self {
+        Self {
+            transactions: BTreeMap::new(),
+            deposited: Some(0),
+            withdrawn: Some(0),
+            charged_back: Some(0),
+            peak_history: 0,
+        }
+    }
+}
+
+/// Add to an amount total, which stays `None` once it overflows.
+fn add(total: Option<u128>, amount: Option<u128>) -> Option<u128> {
+    total?.checked_add(amount?)
+}
+
+impl LedgerStats {
+    /// Count a domain event.
+    pub(crate) fn record(&mut self, event: &DomainEvent) {
+        let (r#type, rejected) = match event {
+            &DomainEvent::Deposited { amount, .. } => {
+                self.deposited = add(self.deposited, Some(amount));
+                ("deposit", None)
+            }
+            &DomainEvent::Withdrew { amount, .. } => {
+                self.withdrawn = add(self.withdrawn, Some(amount));
+                ("withdrawal", None)
+            }
+            DomainEvent::DisputeOpened { .. } => ("dispute", None),
+            DomainEvent::Resolved { .. } => ("resolve", None),
+            &DomainEvent::ChargedBack { amount, .. } => {
+                self.charged_back = add(self.charged_back, Some(amount));
+                ("chargeback", None)
+            }
+            DomainEvent::Rejected { r#type, reason, .. } => (r#type.as_str(), Some(reason)),
+            DomainEvent::AccountOpened { .. }
+            | DomainEvent::AccountFrozen { .. }
+            | DomainEvent::Expired { .. } => return,
+        };
+        let counts = self.transactions.entry(r#type.to_owned()).or_default();
+        match rejected {
+            Some(reason) => *counts.rejected.entry(reason.to_string()).or_default() += 1,
+            None => counts.applied += 1,
+        }
+    }
+
+    /// Add the counts of a ledger holding other clients.
+    pub(crate) fn merge(&mut self, other: &LedgerStats) {
+        for (r#type, other) in &other.transactions {
+            let counts = self.transactions.entry(r#type.clone()).or_default();
+            counts.applied += other.applied;
+            for (reason, count) in &other.rejected {
+                *counts.rejected.entry(reason.clone()).or_default() += count;
+            }
+        }
+        self.deposited = add(self.deposited, other.deposited);
+        self.withdrawn = add(self.withdrawn, other.withdrawn);
+        self.charged_back = add(self.charged_back, other.charged_back);
+        self.peak_history += other.peak_history;
+    }
+
+    /// Number of transactions processed, applied or rejected.
+    pub fn total(&self) -> u64 {
+        self.transactions.values().map(TypeCounts::total).sum()
+    }
+}
+
+/// What a run of the engine did, reported once the input is exhausted.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    /// the policies the ledger applied.
+    pub config: LedgerConfig,
+    pub stats: LedgerStats,
+    pub accounts: usize,
+    pub frozen_accounts: usize,
+    /// wall clock time taken to process the input.
+    pub elapsed_secs: f64,
+    /// transactions processed per second of wall clock time.
+    pub throughput: f64,
+    /// the [`Ledger::state_hash`] of the accounts, in hex.
+    pub state_root: String,
+}
+
+impl RunSummary {
+    /// The summary of a run which took `elapsed` to process its input.
+    pub fn new(ledger: &Ledger, elapsed: Duration) -> Self {
+        let stats = ledger.stats().clone();
+        let elapsed_secs = elapsed.as_secs_f64();
+        let throughput = if elapsed_secs > 0.0 {
+            stats.total() as f64 / elapsed_secs
+        } else {
+            0.0
+        };
+        Self {
+            config: ledger.config().clone(),
+            accounts: ledger.accounts().len(),
+            frozen_accounts: ledger.accounts().iter().filter(|(_, a)| a.frozen).count(),
+            stats,
+            elapsed_secs,
+            throughput,
+            state_root: to_hex(&ledger.state_hash()),
+        }
+    }
+}
+
+impl Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let amount = |amount: Option<u128>| amount.map_or("overflowed".to_owned(), format_amount);
+        let config = serde_json::to_string(&self.config).map_err(|_| std::fmt::Error)?;
+        writeln!(f, "run summary")?;
+        writeln!(f, "  config: {config}")?;
+        writeln!(
+            f,
+            "  transactions: {} in {:.3}s, {:.0} per second",
+            self.stats.total(),
+            self.elapsed_secs,
+            self.throughput
+        )?;
+        for (r#type, counts) in &self.stats.transactions {
+            let rejected = counts.total() - counts.applied;
+            writeln!(
+                f,
+                "    {type}: {} applied, {rejected} rejected",
+                counts.applied
+            )?;
+            for (reason, count) in &counts.rejected {
+                writeln!(f, "      {reason}: {count}")?;
+            }
+        }
+        writeln!(
+            f,
+            "  volume: {} deposited, {} withdrawn, {} charged back",
+            amount(self.stats.deposited),
+            amount(self.stats.withdrawn),
+            amount(self.stats.charged_back)
+        )?;
+        writeln!(
+            f,
+            "  accounts: {}, {} frozen",
+            self.accounts, self.frozen_accounts
+        )?;
+        writeln!(f, "  peak history: {}", self.stats.peak_history)?;
+        write!(f, "  state root: {}", self.state_root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{events::ProcessEvent, ledger::Ledger, observer::DomainEvent, transaction::Txn};
+
+    use super::RunSummary;
+
+    #[test]
+    fn test_run_summary() -> Result<(), ProcessEvent> {
+        let mut ledger = Ledger::new();
+        let txns = [
+            Txn::Deposit {
+                client_id: 1,
+                txn_id: 1,
+                amount: 10_0000,
+            },
+            Txn::Deposit {
+                client_id: 2,
+                txn_id: 2,
+                amount: 5_0000,
+            },
+            Txn::Withdraw {
+                client_id: 2,
+                txn_id: 3,
+                amount: 2_0000,
+            },
+            Txn::Withdraw {
+                client_id: 2,
+                txn_id: 4,
+                amount: 9_0000,
+            },
+            Txn::Dispute {
+                client_id: 1,
+                txn_id: 1,
+            },
+            Txn::ChargeBack {
+                client_id: 1,
+                txn_id: 1,
+            },
+            Txn::Resolve {
+                client_id: 1,
+                txn_id: 1,
+            },
+            Txn::Deposit {
+                client_id: 1,
+                txn_id: 5,
+                amount: 1_0000,
+            },
+        ];
+        for txn in txns {
+            ledger.process_txn(txn)?;
+        }
+
+        let summary = RunSummary::new(&ledger, Duration::from_secs(2));
+        let stats = &summary.stats;
+        assert_eq!(stats.total(), 8);
+        assert_eq!(summary.throughput, 4.0);
+        assert_eq!(stats.transactions["deposit"].applied, 2);
+        assert_eq!(stats.transactions["deposit"].rejected["account frozen"], 1);
+        assert_eq!(
+            stats.transactions["withdrawal"].rejected["insufficient funds"],
+            1
+        );
+        assert_eq!(
+            stats.transactions["resolve"].rejected["referenced txn not in dispute"],
+            1
+        );
+        assert_eq!(stats.deposited, Some(15_0000));
+        assert_eq!(stats.withdrawn, Some(2_0000));
+        assert_eq!(stats.charged_back, Some(10_0000));
+        assert_eq!(stats.peak_history, 2);
+        assert_eq!((summary.accounts, summary.frozen_accounts), (2, 1));
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["stats"]["deposited"], "15.0000");
+
+        // a total which overflows is reported as such, not as the limit
+        let mut stats = summary.stats.clone();
+        stats.record(&DomainEvent::Deposited {
+            client: 3,
+            tx: 6,
+            amount: u128::MAX,
+        });
+        assert_eq!(stats.deposited, None);
+        assert_eq!(stats.withdrawn, Some(2_0000));
+        let mut merged = summary.stats.clone();
+        merged.merge(&stats);
+        assert_eq!(merged.deposited, None);
+        let summary = RunSummary {
+            stats: merged,
+            ..summary
+        };
+        assert!(summary
+            .to_string()
+            .contains("volume: overflowed deposited, 4.0000 withdrawn"));
+        let json = serde_json::to_value(&summary).unwrap();
+        assert!(json["stats"]["deposited"].is_null());
+
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
 use crate::{events::ProcessEvent, record::Record};
 
-#[derive(PartialEq)]
+/// A parsed transaction, amounts in units of 0.0001.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Txn {
     Deposit {
         client_id: u16,
@@ -26,6 +31,116 @@ pub enum Txn {
     },
 }
 
+/// The kinds of transaction which can be disputed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxnKind {
+    Deposit,
+    Withdrawal,
+}
+
+/// Where a transaction is in the dispute lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxnState {
+    Settled,
+    Disputed,
+    ChargedBack,
+}
+
+/// The parts of a transaction needed to dispute it later.
+///
+/// This is what the ledger keeps in its history instead of the
+/// full `Txn`, as it is stored for every deposit in the file.
+/// The amount is split into two words so the entry is 8 byte
+/// aligned rather than 16, which saves 8 bytes per history slot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisputableTxn {
+    amount: [u64; 2],
+    // sequence number of the record which created this entry.
+    pub(crate) seq: u64,
+    pub client_id: u16,
+    pub kind: TxnKind,
+    pub state: TxnState,
+    // set once the entry leaves the dispute window while disputed.
+    pub(crate) expired: bool,
+}
+
+impl DisputableTxn {
+    pub(crate) fn new(kind: TxnKind, client_id: u16, amount: u128, seq: u64) -> Self {
+        Self {
+            amount: [(amount >> 64) as u64, amount as u64],
+            seq,
+            client_id,
+            kind,
+            state: TxnState::Settled,
+            expired: false,
+        }
+    }
+
+    pub fn amount(&self) -> u128 {
+        ((self.amount[0] as u128) << 64) | self.amount[1] as u128
+    }
+}
+
+/// ids in a page of a [`TxnIds`].
+const PAGE_IDS: u32 = 4096;
+
+/// A set of txn ids, one bit per id.
+///
+/// The bits are kept in pages of 4096 ids, allocated as ids are
+/// added, so a dense range of ids costs a bit each and a sparse
+/// set no more than a page per id.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TxnIds {
+    pages: HashMap<u32, Box<[u64; PAGE_IDS as usize / 64]>>,
+}
+
+impl TxnIds {
+    pub(crate) fn insert(&mut self, txn_id: u32) {
+        let (page, word, bit) = Self::position(txn_id);
+        let page = self
+            .pages
+            .entry(page)
+            .or_insert_with(|| Box::new([0; PAGE_IDS as usize / 64]));
+        page[word] |= bit;
+    }
+
+    pub(crate) fn contains(&self, txn_id: u32) -> bool {
+        let (page, word, bit) = Self::position(txn_id);
+        self.pages
+            .get(&page)
+            .is_some_and(|page| page[word] & bit != 0)
+    }
+
+    /// Every id in the set, in id order.
+    pub(crate) fn ids(&self) -> Vec<u32> {
+        let mut pages: Vec<_> = self.pages.iter().collect();
+        pages.sort_unstable_by_key(|(page, _)| **page);
+        pages
+            .into_iter()
+            .flat_map(|(page, words)| {
+                (0..PAGE_IDS)
+                    .filter(|id| words[*id as usize / 64] & (1 << (id % 64)) != 0)
+                    .map(move |id| page * PAGE_IDS + id)
+            })
+            .collect()
+    }
+
+    fn position(txn_id: u32) -> (u32, usize, u64) {
+        let offset = txn_id % PAGE_IDS;
+        (txn_id / PAGE_IDS, offset as usize / 64, 1 << (offset % 64))
+    }
+}
+
+impl Extend<u32> for TxnIds {
+    fn extend<I: IntoIterator<Item = u32>>(&mut self, ids: I) {
+        for txn_id in ids {
+            self.insert(txn_id);
+        }
+    }
+}
+
 impl Txn {
     /// transform deserialised decimal back to string format
     /// with 4 decimals.
@@ -62,6 +177,17 @@ impl Txn {
         }
     }
 
+    /// The name of the transaction type as it appears in the csv.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Deposit { .. } => "deposit",
+            Self::Withdraw { .. } => "withdrawal",
+            Self::Dispute { .. } => "dispute",
+            Self::Resolve { .. } => "resolve",
+            Self::ChargeBack { .. } => "chargeback",
+        }
+    }
+
     pub fn amount(&self) -> u128 {
         match self {
             Self::Deposit { amount, .. } => *amount,
@@ -70,6 +196,10 @@ impl Txn {
         }
     }
 
+    /// Validate a deserialised record as a transaction.
+    ///
+    /// Fails if the type is unrecognised, or a deposit
+    /// or withdrawal has no amount.
     pub fn from_record(input: Record) -> Result<Self, ProcessEvent> {
         let txn_type = input.r#type;
         let client_id = input.client;
@@ -84,9 +214,9 @@ impl Txn {
                         amount,
                     })
                 } else {
-                    return Err(ProcessEvent::ExternalErr(
+                    Err(ProcessEvent::ExternalErr(
                         "deposit needs an amount".to_owned(),
-                    ));
+                    ))
                 }
             }
             "withdrawal" => {
@@ -97,9 +227,9 @@ impl Txn {
                         amount,
                     })
                 } else {
-                    return Err(ProcessEvent::ExternalErr(
+                    Err(ProcessEvent::ExternalErr(
                         "withdrawal needs an amount".to_owned(),
-                    ));
+                    ))
                 }
             }
             "dispute" => Ok(Self::Dispute { client_id, txn_id }),
@@ -114,7 +244,7 @@ impl Txn {
 
 #[cfg(test)]
 mod tests {
-    use crate::transaction::Txn;
+    use crate::transaction::{DisputableTxn, Txn, TxnIds, TxnKind};
 
     #[test]
     fn test_u128_to_decimal_string() {
@@ -138,4 +268,31 @@ mod tests {
         let to_string = Txn::u128_to_decimal_str(value);
         assert_eq!(to_string, Ok(String::from("0.0000")));
     }
+
+    #[test]
+    fn test_disputable_txn_amount() {
+        for amount in [0, 1, u64::MAX as u128, u64::MAX as u128 + 1, u128::MAX] {
+            assert_eq!(
+                DisputableTxn::new(TxnKind::Deposit, 1, amount, 1).amount(),
+                amount
+            );
+        }
+        assert_eq!(std::mem::size_of::<DisputableTxn>(), 32);
+    }
+
+    #[test]
+    fn test_txn_ids() {
+        let mut ids = TxnIds::default();
+        let added = [u32::MAX, 0, 63, 64, 4095, 4096, 1_000_000];
+        ids.extend(added);
+        for txn_id in added {
+            assert!(ids.contains(txn_id));
+        }
+        for txn_id in [1, 62, 65, 4097, u32::MAX - 1] {
+            assert!(!ids.contains(txn_id));
+        }
+        let mut sorted = added.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(ids.ids(), sorted);
+    }
 }
@@ -0,0 +1,281 @@
+//! Exercises the engine through its public API only.
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+
+use toy_txn_engine::{
+    sharded::process_sharded, DisputeWindow, DomainEvent, HttpServer, Ledger, LedgerObserver,
+    PolicyContext, PolicyDecision, ProcessEvent, Record, RejectReason, Txn, TxnPolicy, TxnState,
+};
+
+fn reader(csv: &str) -> csv::Reader<&[u8]> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(csv.as_bytes())
+}
+
+#[test]
+fn test_typed_transactions() -> Result<(), ProcessEvent> {
+    let mut ledger = Ledger::new();
+
+    ledger.process_txn(Txn::Deposit {
+        client_id: 1,
+        txn_id: 1,
+        amount: 10_0000,
+    })?;
+    ledger.process_txn(Txn::Deposit {
+        client_id: 2,
+        txn_id: 2,
+        amount: 5_0000,
+    })?;
+    let outcome = ledger.process_txn(Txn::Withdraw {
+        client_id: 2,
+        txn_id: 3,
+        amount: 4_0000,
+    })?;
+    assert_eq!(outcome, ProcessEvent::ProcessComplete);
+
+    // client 2 disputes client 1's deposit
+    ledger.process_txn(Txn::Dispute {
+        client_id: 2,
+        txn_id: 1,
+    })?;
+    let account = ledger.account(1).unwrap();
+    assert_eq!(account.available, 0);
+    assert_eq!(account.held, 10_0000);
+    assert!(account.disputes.contains(&1));
+    assert_eq!(ledger.txn(1).unwrap().state, TxnState::Disputed);
+    assert_eq!(ledger.txn(1).unwrap().amount(), 10_0000);
+
+    ledger.process_txn(Txn::ChargeBack {
+        client_id: 1,
+        txn_id: 1,
+    })?;
+    assert!(ledger.account(1).unwrap().frozen);
+    assert_eq!(ledger.txn(1).unwrap().state, TxnState::ChargedBack);
+
+    let outcome = ledger.process_txn(Txn::Deposit {
+        client_id: 1,
+        txn_id: 4,
+        amount: 1,
+    })?;
+    assert_eq!(outcome, ProcessEvent::Rejected(RejectReason::AccountFrozen));
+
+    // withdrawals are never kept in the history
+    assert!(ledger.txn(3).is_none());
+    assert_eq!(ledger.history_len(), 2);
+
+    let clients: Vec<u16> = ledger.accounts().iter().map(|(id, _)| id).collect();
+    assert_eq!(clients, vec![1, 2]);
+    Ok(())
+}
+
+#[test]
+fn test_csv_records() -> Result<(), Box<dyn std::error::Error>> {
+    let csv = "type, client, tx, amount
+deposit, 1, 1, 1.0
+deposit, 2, 2, 5.0
+deposit, 2, 3, 5.0
+deposit, 1, 4, 2.0
+withdrawal, 1, 5, 1.5
+withdrawal, 2, 6, 3.0
+dispute, 2, 3,
+";
+    let mut ledger = Ledger::with_dispute_window(DisputeWindow::Transactions(100));
+    for result in reader(csv).deserialize() {
+        let record: Record = result?;
+        ledger.process_transaction(record)?;
+    }
+
+    let mut out = Vec::new();
+    ledger.write_accounts(&mut out)?;
+    let expected = "    client, available,      held,     total,    locked
+         1,    1.5000,    0.0000,    1.5000,     false
+         2,    2.0000,    5.0000,    7.0000,     false
+";
+    assert_eq!(String::from_utf8(out)?, expected);
+
+    let sharded = process_sharded(&mut reader(csv), 2, Ledger::new)?;
+    assert_eq!(sharded.account(2).unwrap().held, 5_0000);
+    Ok(())
+}
+
+// a business rule defined outside the crate.
+struct NoDisputesOnEmptyAccounts;
+
+impl TxnPolicy for NoDisputesOnEmptyAccounts {
+    fn evaluate(&mut self, txn: &Txn, context: &PolicyContext) -> PolicyDecision {
+        let empty = context.account.is_none_or(|account| account.available == 0);
+        if matches!(txn, Txn::Dispute { .. }) && empty {
+            return PolicyDecision::Reject("disputing client has no funds".to_owned());
+        }
+        PolicyDecision::Approve
+    }
+}
+
+#[test]
+fn test_custom_policy() -> Result<(), ProcessEvent> {
+    let mut ledger = Ledger::builder().policy(NoDisputesOnEmptyAccounts).build();
+
+    ledger.process_txn(Txn::Deposit {
+        client_id: 1,
+        txn_id: 1,
+        amount: 10_0000,
+    })?;
+    let outcome = ledger.process_txn(Txn::Dispute {
+        client_id: 2,
+        txn_id: 1,
+    })?;
+    assert_eq!(
+        outcome,
+        ProcessEvent::Rejected(RejectReason::Policy(
+            "disputing client has no funds".to_owned()
+        ))
+    );
+    assert_eq!(ledger.account(1).unwrap().held, 0);
+
+    let outcome = ledger.process_txn(Txn::Dispute {
+        client_id: 1,
+        txn_id: 1,
+    })?;
+    assert_eq!(outcome, ProcessEvent::ProcessComplete);
+    Ok(())
+}
+
+/// send a request and split the response into its status and body.
+fn http(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_owned())
+}
+
+#[test]
+fn test_http_api() -> Result<(), ProcessEvent> {
+    let server = HttpServer::bind("127.0.0.1:0", Ledger::new())?;
+    let addr = server.local_addr()?;
+    thread::spawn(move || server.run());
+
+    let (status, body) = http(
+        addr,
+        "POST",
+        "/transactions",
+        r#"{"type":"deposit","client":1,"tx":1,"amount":"10.5"}"#,
+    );
+    assert_eq!((status, body.as_str()), (200, r#"{"outcome":"applied"}"#));
+
+    let (status, body) = http(
+        addr,
+        "POST",
+        "/transactions",
+        r#"[
+            {"type":"deposit","client":2,"tx":2,"amount":"3"},
+            {"type":"withdrawal","client":2,"tx":3,"amount":"5"},
+            {"type":"dispute","client":1,"tx":1},
+            {"type":"refund","client":1,"tx":1}
+        ]"#,
+    );
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        r#"[{"outcome":"applied"},{"outcome":"rejected","reason":"insufficient funds"},{"outcome":"applied"},{"error":"unrecognised txn: refund"}]"#
+    );
+
+    let (status, body) = http(addr, "GET", "/accounts/1", "");
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        r#"{"client":1,"available":"0.0000","held":"10.5000","total":"10.5000","locked":false}"#
+    );
+
+    let (status, body) = http(addr, "GET", "/accounts", "");
+    assert_eq!(status, 200);
+    let accounts: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+    assert_eq!(accounts.len(), 2);
+    assert_eq!(accounts[1]["available"], "3.0000");
+
+    let (status, body) = http(addr, "GET", "/transactions/1", "");
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        r#"{"tx":1,"client":1,"type":"deposit","amount":"10.5000","state":"disputed"}"#
+    );
+
+    let (status, body) = http(addr, "GET", "/accounts.csv", "");
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        "    client, available,      held,     total,    locked
+         1,    0.0000,   10.5000,   10.5000,     false
+         2,    3.0000,    0.0000,    3.0000,     false
+"
+    );
+
+    assert_eq!(http(addr, "GET", "/accounts/9", "").0, 404);
+    assert_eq!(http(addr, "GET", "/transactions/3", "").0, 404);
+    assert_eq!(http(addr, "GET", "/accounts/x", "").0, 400);
+    assert_eq!(http(addr, "POST", "/transactions", "{").0, 400);
+    assert_eq!(http(addr, "DELETE", "/accounts/1", "").0, 405);
+    assert_eq!(http(addr, "GET", "/ledger", "").0, 404);
+    let (status, body) = http(
+        addr,
+        "POST",
+        "/transactions",
+        r#"{"type":"refund","client":1,"tx":1}"#,
+    );
+    assert_eq!(
+        (status, body.as_str()),
+        (400, r#"{"error":"unrecognised txn: refund"}"#)
+    );
+
+    // a ledger which fails is a server error, for a batch as well
+    struct FailingJournal;
+    impl LedgerObserver for FailingJournal {
+        fn on_event(&mut self, _seq: u64, event: &DomainEvent) -> Result<(), ProcessEvent> {
+            match event {
+                DomainEvent::Deposited { tx: 90.., .. } => {
+                    Err(ProcessEvent::ExternalErr("journal full".to_owned()))
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+    let mut ledger = Ledger::new();
+    ledger.subscribe(FailingJournal);
+    let server = HttpServer::bind("127.0.0.1:0", ledger)?;
+    let addr = server.local_addr()?;
+    thread::spawn(move || server.run());
+
+    let (status, body) = http(
+        addr,
+        "POST",
+        "/transactions",
+        r#"{"type":"deposit","client":1,"tx":99,"amount":"1"}"#,
+    );
+    assert_eq!(
+        (status, body.as_str()),
+        (500, r#"{"error":"journal full"}"#)
+    );
+    let (status, body) = http(
+        addr,
+        "POST",
+        "/transactions",
+        r#"[
+            {"type":"deposit","client":1,"tx":1,"amount":"1"},
+            {"type":"deposit","client":1,"tx":98,"amount":"1"},
+            {"type":"deposit","client":1,"tx":2,"amount":"1"}
+        ]"#,
+    );
+    assert_eq!(
+        (status, body.as_str()),
+        (500, r#"{"error":"journal full, after 1 of 3 records"}"#)
+    );
+    Ok(())
+}